@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS schedules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    category_id INTEGER,
+    kind TEXT,
+    cron TEXT,
+    jitter INTEGER DEFAULT 0,
+    catch_up TEXT DEFAULT 'skip',
+    max_catch_up INTEGER DEFAULT 10,
+    active BOOLEAN DEFAULT true,
+    last_run DATETIME
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS api_keys (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT,
+    prefix TEXT,
+    hash TEXT UNIQUE,
+    created_at DATETIME,
+    last_used_at DATETIME,
+    revoked_at DATETIME
+);
@@ -0,0 +1 @@
+ALTER TABLE categories ADD COLUMN backend TEXT DEFAULT 'telegram';
@@ -0,0 +1,2 @@
+ALTER TABLE categories DROP COLUMN mastodon_url;
+ALTER TABLE categories DROP COLUMN mastodon_token;
@@ -0,0 +1,2 @@
+ALTER TABLE categories ADD COLUMN mastodon_url TEXT;
+ALTER TABLE categories ADD COLUMN mastodon_token TEXT;
@@ -0,0 +1,3 @@
+ALTER TABLE categories DROP COLUMN matrix_homeserver;
+ALTER TABLE categories DROP COLUMN matrix_room_id;
+ALTER TABLE categories DROP COLUMN matrix_token;
@@ -0,0 +1,3 @@
+ALTER TABLE categories ADD COLUMN matrix_homeserver TEXT;
+ALTER TABLE categories ADD COLUMN matrix_room_id TEXT;
+ALTER TABLE categories ADD COLUMN matrix_token TEXT;
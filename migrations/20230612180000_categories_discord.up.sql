@@ -0,0 +1 @@
+ALTER TABLE categories ADD COLUMN discord_webhook TEXT;
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS publications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT,
+    item_id INTEGER,
+    category_id INTEGER,
+    backend TEXT,
+    chat_id TEXT,
+    thread_id INTEGER,
+    message_id TEXT,
+    published_at DATETIME,
+    outcome TEXT,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS publications_category_published_at
+    ON publications (category_id, published_at);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS templates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    category_id INTEGER,
+    kind TEXT,
+    content TEXT,
+    UNIQUE (category_id, kind)
+);
@@ -0,0 +1,5 @@
+ALTER TABLE answers ADD COLUMN position INTEGER DEFAULT 0;
+UPDATE answers SET position = (
+    SELECT COUNT(*) FROM answers AS previous
+    WHERE previous.poll_id = answers.poll_id AND previous.id < answers.id
+);
@@ -0,0 +1,11 @@
+DROP TRIGGER IF EXISTS answers_fts_update;
+DROP TRIGGER IF EXISTS answers_fts_delete;
+DROP TRIGGER IF EXISTS answers_fts_insert;
+DROP TRIGGER IF EXISTS polls_fts_update;
+DROP TRIGGER IF EXISTS polls_fts_delete;
+DROP TRIGGER IF EXISTS polls_fts_insert;
+DROP TABLE IF EXISTS polls_fts;
+DROP TRIGGER IF EXISTS tips_fts_update;
+DROP TRIGGER IF EXISTS tips_fts_delete;
+DROP TRIGGER IF EXISTS tips_fts_insert;
+DROP TABLE IF EXISTS tips_fts;
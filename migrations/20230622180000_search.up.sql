@@ -0,0 +1,69 @@
+-- Tips are indexed from their own table
+CREATE VIRTUAL TABLE tips_fts USING fts5(
+    title,
+    text,
+    content = 'tips',
+    content_rowid = 'id'
+);
+
+CREATE TRIGGER tips_fts_insert AFTER INSERT ON tips BEGIN
+    INSERT INTO tips_fts (rowid, title, text) VALUES (new.id, new.title, new.text);
+END;
+
+CREATE TRIGGER tips_fts_delete AFTER DELETE ON tips BEGIN
+    INSERT INTO tips_fts (tips_fts, rowid, title, text)
+    VALUES ('delete', old.id, old.title, old.text);
+END;
+
+CREATE TRIGGER tips_fts_update AFTER UPDATE OF title, text ON tips BEGIN
+    INSERT INTO tips_fts (tips_fts, rowid, title, text)
+    VALUES ('delete', old.id, old.title, old.text);
+    INSERT INTO tips_fts (rowid, title, text) VALUES (new.id, new.title, new.text);
+END;
+
+INSERT INTO tips_fts (tips_fts) VALUES ('rebuild');
+
+-- Polls are indexed with the texts of their answers, so the index keeps its
+-- own copy of the content
+CREATE VIRTUAL TABLE polls_fts USING fts5(
+    question,
+    answers
+);
+
+CREATE TRIGGER polls_fts_insert AFTER INSERT ON polls BEGIN
+    INSERT INTO polls_fts (rowid, question, answers) VALUES (new.id, new.question, '');
+END;
+
+CREATE TRIGGER polls_fts_delete AFTER DELETE ON polls BEGIN
+    DELETE FROM polls_fts WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER polls_fts_update AFTER UPDATE OF question ON polls BEGIN
+    UPDATE polls_fts SET question = new.question WHERE rowid = new.id;
+END;
+
+CREATE TRIGGER answers_fts_insert AFTER INSERT ON answers BEGIN
+    UPDATE polls_fts SET answers = (
+        SELECT COALESCE(group_concat(text, ' '), '') FROM answers WHERE poll_id = new.poll_id
+    ) WHERE rowid = new.poll_id;
+END;
+
+CREATE TRIGGER answers_fts_delete AFTER DELETE ON answers BEGIN
+    UPDATE polls_fts SET answers = (
+        SELECT COALESCE(group_concat(text, ' '), '') FROM answers WHERE poll_id = old.poll_id
+    ) WHERE rowid = old.poll_id;
+END;
+
+CREATE TRIGGER answers_fts_update AFTER UPDATE OF text, poll_id ON answers BEGIN
+    UPDATE polls_fts SET answers = (
+        SELECT COALESCE(group_concat(text, ' '), '') FROM answers WHERE poll_id = old.poll_id
+    ) WHERE rowid = old.poll_id;
+    UPDATE polls_fts SET answers = (
+        SELECT COALESCE(group_concat(text, ' '), '') FROM answers WHERE poll_id = new.poll_id
+    ) WHERE rowid = new.poll_id;
+END;
+
+INSERT INTO polls_fts (rowid, question, answers)
+SELECT id, question, (
+    SELECT COALESCE(group_concat(text, ' '), '') FROM answers WHERE poll_id = polls.id
+) FROM polls;
@@ -0,0 +1,7 @@
+-- Last category published, for each kind of content, the rotation goes on
+-- from the one after it
+CREATE TABLE IF NOT EXISTS rotations (
+    kind TEXT PRIMARY KEY,
+    category_id INTEGER NOT NULL,
+    updated_at DATETIME
+);
@@ -0,0 +1,3 @@
+ALTER TABLE polls DROP COLUMN priority;
+ALTER TABLE tips DROP COLUMN priority;
+ALTER TABLE categories DROP COLUMN strategy;
@@ -0,0 +1,3 @@
+ALTER TABLE categories ADD COLUMN strategy TEXT DEFAULT 'fifo';
+ALTER TABLE tips ADD COLUMN priority INTEGER DEFAULT 0;
+ALTER TABLE polls ADD COLUMN priority INTEGER DEFAULT 0;
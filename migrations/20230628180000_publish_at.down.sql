@@ -0,0 +1,2 @@
+ALTER TABLE polls DROP COLUMN publish_at;
+ALTER TABLE tips DROP COLUMN publish_at;
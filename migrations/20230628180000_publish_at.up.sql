@@ -0,0 +1,2 @@
+ALTER TABLE tips ADD COLUMN publish_at DATETIME;
+ALTER TABLE polls ADD COLUMN publish_at DATETIME;
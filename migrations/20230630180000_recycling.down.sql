@@ -0,0 +1,6 @@
+ALTER TABLE polls DROP COLUMN last_published_at;
+ALTER TABLE polls DROP COLUMN published_count;
+ALTER TABLE tips DROP COLUMN last_published_at;
+ALTER TABLE tips DROP COLUMN published_count;
+ALTER TABLE categories DROP COLUMN cooldown;
+ALTER TABLE categories DROP COLUMN recycle;
@@ -0,0 +1,16 @@
+ALTER TABLE categories ADD COLUMN recycle BOOLEAN DEFAULT false;
+ALTER TABLE categories ADD COLUMN cooldown INTEGER DEFAULT 30;
+ALTER TABLE tips ADD COLUMN published_count INTEGER DEFAULT 0;
+ALTER TABLE tips ADD COLUMN last_published_at DATETIME;
+ALTER TABLE polls ADD COLUMN published_count INTEGER DEFAULT 0;
+ALTER TABLE polls ADD COLUMN last_published_at DATETIME;
+UPDATE tips SET
+    published_count = (SELECT COUNT(*) FROM publications
+                       WHERE kind = 'tip' AND item_id = tips.id AND outcome = 'sent'),
+    last_published_at = (SELECT MAX(published_at) FROM publications
+                         WHERE kind = 'tip' AND item_id = tips.id AND outcome = 'sent');
+UPDATE polls SET
+    published_count = (SELECT COUNT(*) FROM publications
+                       WHERE kind = 'poll' AND item_id = polls.id AND outcome = 'sent'),
+    last_published_at = (SELECT MAX(published_at) FROM publications
+                         WHERE kind = 'poll' AND item_id = polls.id AND outcome = 'sent');
@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS publish_jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT,
+    item_id INTEGER,
+    category_id INTEGER,
+    state TEXT DEFAULT 'pending',
+    attempts INTEGER DEFAULT 0,
+    message_id TEXT,
+    error TEXT,
+    created_at DATETIME,
+    updated_at DATETIME
+);
+-- An item has a single job that is not sent, so it can't be sent twice at
+-- the same time
+CREATE UNIQUE INDEX IF NOT EXISTS publish_jobs_open
+    ON publish_jobs (kind, item_id) WHERE state IN ('pending', 'claimed', 'failed');
+CREATE INDEX IF NOT EXISTS publish_jobs_state
+    ON publish_jobs (state, id);
@@ -0,0 +1,251 @@
+use std::{fs, path::{Path, PathBuf}, str::FromStr, time::Duration};
+use clap::{Parser, Subcommand};
+use sqlx::SqlitePool;
+use tracing::info;
+
+use crate::{
+    database,
+    http::{
+        self,
+        AppState,
+        publish::{publish_next_tip, publish_next_poll},
+    },
+    models::{
+        backup::{Backup, Format},
+        category::{Category, NewCategory},
+        import::{self, Document},
+        kind::Kind,
+        publisher::Backend,
+        rate_limit::Limits,
+        rotation::Rotation,
+        telegram::{self, Telegram},
+        validation::Validate,
+    },
+};
+
+/// Publishes tips and polls in Telegram, Mastodon, Matrix and Discord. Every
+/// option can be set with the environment variable shown in its help.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli{
+    #[arg(long, env = "LOG_LEVEL", default_value = "DEBUG", global = true)]
+    pub log_level: String,
+    #[arg(long, env = "DB_URL", default_value = "publirs.db", global = true)]
+    db_url: String,
+    /// In PRODUCTION the migrations, templates and assets are read from the
+    /// directory of the binary, otherwise from the directory of the crate
+    #[arg(long, env = "ENVIRONMENT", default_value = "DEVELOPMENT", global = true)]
+    environment: String,
+    /// Token of the Telegram bot, needed to serve and to publish
+    #[arg(long, env = "TOKEN", hide_env_values = true, global = true)]
+    token: Option<String>,
+    #[arg(long, env = "TELEGRAM_API_URL", default_value = telegram::DEFAULT_BASE_URL, global = true)]
+    telegram_api_url: String,
+    /// Messages per second sent to Telegram, to all the chats together
+    #[arg(long, env = "TELEGRAM_RATE", default_value = "30",
+        value_parser = clap::value_parser!(u32).range(1..), global = true)]
+    telegram_rate: u32,
+    /// Messages per minute sent to a single chat of Telegram
+    #[arg(long, env = "TELEGRAM_CHAT_RATE", default_value = "20",
+        value_parser = clap::value_parser!(u32).range(1..), global = true)]
+    telegram_chat_rate: u32,
+    /// Seconds to keep retrying a message Telegram refuses for flood control
+    /// or fails to receive, 0 to not retry
+    #[arg(long, env = "TELEGRAM_RETRY_BUDGET", default_value = "60", global = true)]
+    telegram_retry_budget: u64,
+    /// Port of the HTTP server
+    #[arg(long, env = "PORT", default_value = "8080", global = true)]
+    port: u16,
+    /// Key always accepted by the HTTP server, to create the first keys
+    #[arg(long, env = "API_KEY", hide_env_values = true, global = true)]
+    api_key: Option<String>,
+    /// Seed of the random strategies, to choose the same items every time
+    #[arg(long, env = "SEED", global = true)]
+    seed: Option<u64>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command{
+    /// Starts the HTTP server and the scheduler, it is the default command
+    Serve,
+    /// Applies the pending migrations to the database
+    Migrate,
+    /// Publishes the first tip or poll not published yet
+    Publish{
+        /// tip or poll
+        #[arg(value_parser = parse::<Kind>)]
+        kind: Kind,
+        /// Only publish items of the category with this name
+        #[arg(long)]
+        category: Option<String>,
+        /// Publish from the category after the one of the last time, taking
+        /// turns between the categories with items not published. When there
+        /// are none, an item of a category that recycles is published again
+        #[arg(long, conflicts_with = "category")]
+        next: bool,
+    },
+    /// Imports categories, tips and polls from a YAML or a JSON file
+    Import{
+        file: PathBuf,
+    },
+    /// Writes a backup of the categories, tips, polls and answers
+    Export{
+        /// json or yaml
+        #[arg(long, default_value = "json", value_parser = parse::<Format>)]
+        format: Format,
+        /// File to write, the standard output by default
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Manages the categories
+    Category{
+        #[command(subcommand)]
+        command: CategoryCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum CategoryCommand{
+    /// Lists the categories
+    List,
+    /// Adds a category, the settings of Mastodon, Matrix and Discord can be
+    /// set later with the HTTP API or the web
+    Add{
+        name: String,
+        #[arg(long)]
+        chat_id: String,
+        #[arg(long, default_value = "0")]
+        thread_id: i64,
+        /// telegram, mastodon, matrix or discord
+        #[arg(long, default_value = "telegram", value_parser = parse::<Backend>)]
+        backend: Backend,
+    },
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String>{
+    value.parse().map_err(|_| format!("{} is not valid", value))
+}
+
+/// Whether the file is YAML, otherwise it is read as JSON
+fn is_yaml(file: &Path) -> bool{
+    matches!(file.extension().and_then(|extension| extension.to_str()),
+        Some("yaml") | Some("yml"))
+}
+
+impl Cli{
+    /// Directory with the migrations, the templates and the assets
+    fn resources(&self) -> PathBuf{
+        if self.environment == "PRODUCTION"{
+            info!("PRODUCTION");
+            std::env::current_exe().unwrap().parent().unwrap().to_path_buf()
+        }else{
+            info!("DEVELOPMENT");
+            let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+            Path::new(&crate_dir).to_path_buf()
+        }
+    }
+
+    fn telegram(&self) -> anyhow::Result<Telegram>{
+        let token = self.token.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("TOKEN is mandatory"))?;
+        info!("Telegram API URL: {}", &self.telegram_api_url);
+        Ok(Telegram::new(token, &self.telegram_api_url)
+            .with_limits(Limits{
+                global: self.telegram_rate,
+                chat: self.telegram_chat_rate,
+            })
+            .with_retry_budget(Duration::from_secs(self.telegram_retry_budget)))
+    }
+
+    fn app_state(&self, pool: &SqlitePool, resources: &Path, api_key: Option<String>) -> anyhow::Result<AppState>{
+        let app_state = AppState::new(pool, self.telegram()?, api_key,
+            &resources.join("templates"));
+        Ok(match self.seed{
+            Some(seed) => app_state.with_seed(seed),
+            None => app_state,
+        })
+    }
+
+    pub async fn run(self) -> anyhow::Result<()>{
+        info!("Database URL: {}", &self.db_url);
+        let resources = self.resources();
+        let pool = database::connect(&self.db_url).await?;
+        database::migrate(&pool, &resources.join("migrations")).await?;
+
+        match self.command.as_ref().unwrap_or(&Command::Serve){
+            Command::Serve => {
+                info!("Port: {}", &self.port);
+                let api_key = self.api_key.clone().filter(|key| !key.is_empty());
+                if api_key.is_none(){
+                    info!("API_KEY not set, only keys stored in the database are accepted");
+                }
+                info!("🚀 Server started successfully");
+                let app_state = self.app_state(&pool, &resources, api_key)?;
+                http::serve(app_state, &resources, self.port).await?;
+            },
+            Command::Migrate => {
+                println!("Database migrated");
+            },
+            Command::Publish{kind, category, next} => {
+                let category_id = match category{
+                    Some(name) => Some(Category::search(&pool, name)
+                        .await
+                        .map_err(|_| anyhow::anyhow!("Category {} not found", name))?
+                        .get_id()),
+                    // Without anything new, what can be recycled in any
+                    // category is published
+                    None if *next => Rotation::next(&pool, *kind).await?,
+                    None => None,
+                };
+                let app_state = self.app_state(&pool, &resources, None)?;
+                match kind{
+                    Kind::Tip => {
+                        let tip = publish_next_tip(&app_state, category_id).await?;
+                        println!("Published tip {}: {}", tip.get_id(), tip.get_title());
+                    },
+                    Kind::Poll => {
+                        let poll = publish_next_poll(&app_state, category_id).await?;
+                        println!("Published poll {}: {}", poll.get_id(), poll.get_question());
+                    },
+                }
+            },
+            Command::Import{file} => {
+                let content = fs::read_to_string(file)?;
+                let document = if is_yaml(file){
+                    Document::from_yaml(&content)?
+                }else{
+                    Document::from_json(&content)?
+                };
+                let report = import::import(&pool, document).await?;
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            },
+            Command::Export{format, output} => {
+                let backup = Backup::export(&pool).await?.write(*format)?;
+                match output{
+                    Some(output) => fs::write(output, backup)?,
+                    None => print!("{}", backup),
+                }
+            },
+            Command::Category{command: CategoryCommand::List} => {
+                for category in Category::read_all(&pool).await?{
+                    println!("{}\t{}\t{}\t{}\t{}",
+                        category.get_id(),
+                        category.get_name(),
+                        category.get_backend(),
+                        category.get_chat_id(),
+                        category.get_thread_id());
+                }
+            },
+            Command::Category{command: CategoryCommand::Add{name, chat_id, thread_id, backend}} => {
+                let new_category = NewCategory::new(name.clone(), chat_id.clone(),
+                    *thread_id, *backend);
+                new_category.validate()?;
+                let category = Category::create(&pool, new_category).await?;
+                println!("Created category {}: {}", category.get_id(), category.get_name());
+            },
+        }
+        Ok(())
+    }
+}
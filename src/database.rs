@@ -0,0 +1,50 @@
+use std::path::Path;
+use sqlx::{
+    Sqlite,
+    SqlitePool,
+    sqlite::SqlitePoolOptions,
+    migrate::{Migrator, MigrateDatabase}
+};
+use tracing::{debug, info};
+
+/// Connects to the database, creating it if it doesn't exist
+pub async fn connect(db_url: &str) -> anyhow::Result<SqlitePool>{
+    if !Sqlite::database_exists(db_url).await?{
+        info!("Creating database {}", db_url);
+        Sqlite::create_database(db_url).await?;
+    }
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect(db_url)
+        .await
+        .map_err(|err| {
+            tracing::error!("🔥 Failed to connect to the database: {:?}", err);
+            err
+        })?;
+    info!("✅Connection to the database is successful!");
+    Ok(pool)
+}
+
+/// Applies the migrations not applied yet
+pub async fn migrate(pool: &SqlitePool, migrations: &Path) -> anyhow::Result<()>{
+    debug!("Migrations: {:?}", migrations);
+    Migrator::new(migrations)
+        .await?
+        .run(pool)
+        .await?;
+    Ok(())
+}
+
+/// A database in memory with every migration applied, for the tests
+#[cfg(test)]
+pub async fn memory() -> SqlitePool{
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    migrate(&pool, &Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations"))
+        .await
+        .unwrap();
+    pool
+}
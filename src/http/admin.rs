@@ -0,0 +1,549 @@
+use std::{sync::Arc, collections::HashMap, fmt::Display};
+use axum::{
+    Router,
+    Form,
+    extract::{State, Path, Query},
+    routing,
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use chrono::{DateTime, Utc};
+use minijinja::context;
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+
+use crate::{
+    http::{
+        AppState,
+        publish::{publish_next_tip, publish_next_poll},
+    },
+    models::{
+        answer::{Answer, NewBasicAnswer},
+        category::{Category, NewCategory},
+        poll::{Poll, NewPoll, PollWithAnswers},
+        publish_at,
+        publisher::Backend,
+        strategy::Strategy,
+        tip::{Tip, NewTip},
+        validation::{Errors, Validate, MAX_QUESTION_LENGTH},
+        error::CustomError,
+    },
+};
+
+/// Backends offered in the form of the categories
+const BACKENDS: [Backend; 4] = [Backend::Telegram, Backend::Mastodon, Backend::Matrix, Backend::Discord];
+/// Strategies offered in the form of the categories
+const STRATEGIES: [Strategy; 4] = [Strategy::Fifo, Strategy::Random, Strategy::Priority, Strategy::Weighted];
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/",
+            routing::get(index)
+        )
+        .route("/categories",
+            routing::get(categories)
+        )
+        .route("/categories",
+            routing::post(create_category)
+        )
+        .route("/categories/new",
+            routing::get(new_category)
+        )
+        .route("/categories/:id",
+            routing::get(edit_category)
+        )
+        .route("/categories/:id",
+            routing::post(update_category)
+        )
+        .route("/categories/:id/delete",
+            routing::post(delete_category)
+        )
+        .route("/categories/:id/reset",
+            routing::post(reset_category)
+        )
+        .route("/tips",
+            routing::get(tips)
+        )
+        .route("/tips",
+            routing::post(create_tip)
+        )
+        .route("/tips/new",
+            routing::get(new_tip)
+        )
+        .route("/tips/:id",
+            routing::get(edit_tip)
+        )
+        .route("/tips/:id",
+            routing::post(update_tip)
+        )
+        .route("/tips/:id/delete",
+            routing::post(delete_tip)
+        )
+        .route("/polls",
+            routing::get(polls)
+        )
+        .route("/polls",
+            routing::post(create_poll)
+        )
+        .route("/polls/new",
+            routing::get(new_poll)
+        )
+        .route("/polls/:id",
+            routing::get(edit_poll)
+        )
+        .route("/polls/:id",
+            routing::post(update_poll)
+        )
+        .route("/polls/:id/delete",
+            routing::post(delete_poll)
+        )
+        .route("/queue",
+            routing::get(queue)
+        )
+        .route("/queue/tip",
+            routing::post(publish_tip)
+        )
+        .route("/queue/poll",
+            routing::post(publish_poll)
+        )
+}
+
+/// Message shown after an action, it travels in the query of the redirect
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Flash{
+    message: Option<String>,
+    error: Option<String>,
+}
+
+/// A poll as edited in the form, one answer per line and the position of
+/// the right one starting at 1
+#[derive(Debug, Serialize, Deserialize)]
+struct PollForm{
+    category_id: i64,
+    question: String,
+    answers: String,
+    correct: usize,
+    #[serde(default)]
+    published: bool,
+    #[serde(default)]
+    priority: i64,
+    #[serde(default, deserialize_with = "publish_at::deserialize")]
+    publish_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PublishForm{
+    category_id: i64,
+}
+
+impl PollForm{
+    fn from_poll(poll: &Poll, answers: &[Answer]) -> Self{
+        Self{
+            category_id: poll.get_category_id(),
+            question: poll.get_question().to_string(),
+            answers: answers.iter()
+                .map(|answer| answer.get_text())
+                .collect::<Vec<&str>>()
+                .join("\n"),
+            correct: answers.iter()
+                .position(|answer| answer.get_isok())
+                .map(|position| position + 1)
+                .unwrap_or(1),
+            published: poll.get_published(),
+            priority: poll.get_priority(),
+            publish_at: poll.get_publish_at(),
+        }
+    }
+
+    /// The answers with the right one marked
+    fn answers(&self) -> Vec<NewBasicAnswer>{
+        self.answers.lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .enumerate()
+            .map(|(position, text)| NewBasicAnswer{
+                text: text.to_string(),
+                isok: position + 1 == self.correct,
+            })
+            .collect()
+    }
+}
+
+impl Validate for PollForm{
+    fn check(&self, errors: &mut Errors){
+        errors.text("question", &self.question, MAX_QUESTION_LENGTH);
+        let answers = self.answers();
+        errors.answers(answers.iter().map(|answer| (answer.text.as_str(), answer.isok)));
+    }
+}
+
+fn render<S: Serialize>(app_state: &AppState, name: &str, ctx: S) -> Result<Response, CustomError>{
+    app_state.templates
+        .get_template(name)
+        .and_then(|template| template.render(ctx))
+        .map(|html| Html(html).into_response())
+        .map_err(|e| {
+            tracing::error!("Error rendering {}: {}", name, e);
+            CustomError::ServerError(e.to_string())
+        })
+}
+
+/// Goes back to a page showing the result of the action
+fn redirect(path: &str, flash: Flash) -> Response{
+    let query = serde_urlencoded::to_string(&flash).unwrap_or_default();
+    if query.is_empty() {
+        Redirect::to(path).into_response()
+    }else{
+        Redirect::to(&format!("{}?{}", path, query)).into_response()
+    }
+}
+
+fn done(path: &str, message: String) -> Response{
+    redirect(path, Flash{message: Some(message), error: None})
+}
+
+fn failed<E: Display>(path: &str, error: E) -> Response{
+    tracing::error!("Error: {}", error);
+    redirect(path, Flash{message: None, error: Some(error.to_string())})
+}
+
+async fn category_names(app_state: &AppState) -> Result<HashMap<i64, String>, CustomError>{
+    Ok(Category::read_all(&app_state.pool).await?
+        .into_iter()
+        .map(|category| (category.get_id(), category.get_name().to_string()))
+        .collect())
+}
+
+async fn index(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, CustomError>{
+    let categories = Category::read_all(&app_state.pool).await?;
+    let tips = Tip::read_all(&app_state.pool).await?;
+    let polls = Poll::read_all(&app_state.pool).await?;
+    render(&app_state, "index.html", context!{
+        categories => categories.len(),
+        tips => tips.len(),
+        pending_tips => tips.iter().filter(|tip| !tip.get_published()).count(),
+        polls => polls.len(),
+        pending_polls => polls.iter().filter(|poll| !poll.get_published()).count(),
+    })
+}
+
+async fn categories(
+    State(app_state): State<Arc<AppState>>,
+    Query(flash): Query<Flash>,
+) -> Result<impl IntoResponse, CustomError>{
+    let categories = Category::read_all(&app_state.pool).await?;
+    render(&app_state, "categories.html", context!{
+        categories => categories,
+        flash => flash,
+    })
+}
+
+async fn new_category(
+    State(app_state): State<Arc<AppState>>,
+    Query(flash): Query<Flash>,
+) -> Result<impl IntoResponse, CustomError>{
+    render(&app_state, "category_form.html", context!{
+        backends => BACKENDS,
+        strategies => STRATEGIES,
+        flash => flash,
+    })
+}
+
+async fn create_category(
+    State(app_state): State<Arc<AppState>>,
+    Form(new_category): Form<NewCategory>,
+) -> impl IntoResponse{
+    if let Err(e) = new_category.validate(){
+        return failed("/categories/new", e);
+    }
+    match Category::create(&app_state.pool, new_category).await{
+        Ok(category) => done("/categories", format!("Category {} created", category.get_name())),
+        Err(e) => failed("/categories/new", e),
+    }
+}
+
+async fn edit_category(
+    State(app_state): State<Arc<AppState>>,
+    Path(category_id): Path<i64>,
+    Query(flash): Query<Flash>,
+) -> Result<impl IntoResponse, CustomError>{
+    let category = Category::read(&app_state.pool, category_id).await?;
+    render(&app_state, "category_form.html", context!{
+        category => category,
+        backends => BACKENDS,
+        strategies => STRATEGIES,
+        flash => flash,
+    })
+}
+
+async fn update_category(
+    State(app_state): State<Arc<AppState>>,
+    Path(category_id): Path<i64>,
+    Form(mut category): Form<Category>,
+) -> impl IntoResponse{
+    let path = format!("/categories/{}", category_id);
+    if category.get_id() != category_id {
+        return failed(&path, CustomError::BadRequest);
+    }
+    match Category::read(&app_state.pool, category_id).await{
+        Ok(stored) => category.keep_secrets(stored),
+        Err(e) => return failed(&path, e),
+    }
+    if let Err(e) = category.validate(){
+        return failed(&path, e);
+    }
+    match Category::update(&app_state.pool, category).await{
+        Ok(category) => done("/categories", format!("Category {} updated", category.get_name())),
+        Err(e) => failed(&path, e),
+    }
+}
+
+async fn delete_category(
+    State(app_state): State<Arc<AppState>>,
+    Path(category_id): Path<i64>,
+) -> impl IntoResponse{
+    match Category::delete(&app_state.pool, category_id).await{
+        Ok(category) => done("/categories", format!("Category {} deleted", category.get_name())),
+        Err(e) => failed("/categories", e),
+    }
+}
+
+async fn reset_category(
+    State(app_state): State<Arc<AppState>>,
+    Path(category_id): Path<i64>,
+) -> impl IntoResponse{
+    match Category::reset(&app_state.pool, category_id).await{
+        Ok(reset) => done("/categories", format!("{} tips and {} polls queued again",
+            reset.tips, reset.polls)),
+        Err(e) => failed("/categories", e),
+    }
+}
+
+async fn tips(
+    State(app_state): State<Arc<AppState>>,
+    Query(flash): Query<Flash>,
+) -> Result<impl IntoResponse, CustomError>{
+    let names = category_names(&app_state).await?;
+    let tips: Vec<Value> = Tip::read_all(&app_state.pool).await?
+        .into_iter()
+        .map(|tip| json!({
+            "category": names.get(&tip.get_category_id()),
+            "tip": tip,
+        }))
+        .collect();
+    render(&app_state, "tips.html", context!{
+        tips => tips,
+        flash => flash,
+    })
+}
+
+async fn new_tip(
+    State(app_state): State<Arc<AppState>>,
+    Query(flash): Query<Flash>,
+) -> Result<impl IntoResponse, CustomError>{
+    let categories = Category::read_all(&app_state.pool).await?;
+    render(&app_state, "tip_form.html", context!{
+        categories => categories,
+        flash => flash,
+    })
+}
+
+async fn create_tip(
+    State(app_state): State<Arc<AppState>>,
+    Form(new_tip): Form<NewTip>,
+) -> impl IntoResponse{
+    if let Err(e) = new_tip.validate(){
+        return failed("/tips/new", e);
+    }
+    match Tip::create(&app_state.pool, new_tip).await{
+        Ok(tip) => done("/tips", format!("Tip {} created", tip.get_title())),
+        Err(e) => failed("/tips/new", e),
+    }
+}
+
+async fn edit_tip(
+    State(app_state): State<Arc<AppState>>,
+    Path(tip_id): Path<i64>,
+    Query(flash): Query<Flash>,
+) -> Result<impl IntoResponse, CustomError>{
+    let tip = Tip::read(&app_state.pool, tip_id).await?
+        .ok_or(CustomError::NotFound)?;
+    let categories = Category::read_all(&app_state.pool).await?;
+    render(&app_state, "tip_form.html", context!{
+        tip => tip,
+        categories => categories,
+        flash => flash,
+    })
+}
+
+async fn update_tip(
+    State(app_state): State<Arc<AppState>>,
+    Path(tip_id): Path<i64>,
+    Form(tip): Form<Tip>,
+) -> impl IntoResponse{
+    let path = format!("/tips/{}", tip_id);
+    if tip.get_id() != tip_id {
+        return failed(&path, CustomError::BadRequest);
+    }
+    if let Err(e) = tip.validate(){
+        return failed(&path, e);
+    }
+    match Tip::update(&app_state.pool, tip).await{
+        Ok(tip) => done("/tips", format!("Tip {} updated", tip.get_title())),
+        Err(e) => failed(&path, e),
+    }
+}
+
+async fn delete_tip(
+    State(app_state): State<Arc<AppState>>,
+    Path(tip_id): Path<i64>,
+) -> impl IntoResponse{
+    match Tip::delete(&app_state.pool, tip_id).await{
+        Ok(tip) => done("/tips", format!("Tip {} deleted", tip.get_title())),
+        Err(e) => failed("/tips", e),
+    }
+}
+
+async fn polls(
+    State(app_state): State<Arc<AppState>>,
+    Query(flash): Query<Flash>,
+) -> Result<impl IntoResponse, CustomError>{
+    let names = category_names(&app_state).await?;
+    let answers = Answer::count_by_poll(&app_state.pool).await?;
+    let polls: Vec<Value> = Poll::read_all(&app_state.pool).await?
+        .into_iter()
+        .map(|poll| json!({
+            "category": names.get(&poll.get_category_id()),
+            "answers": answers.get(&poll.get_id()).copied().unwrap_or(0),
+            "poll": poll,
+        }))
+        .collect();
+    render(&app_state, "polls.html", context!{
+        polls => polls,
+        flash => flash,
+    })
+}
+
+async fn new_poll(
+    State(app_state): State<Arc<AppState>>,
+    Query(flash): Query<Flash>,
+) -> Result<impl IntoResponse, CustomError>{
+    let categories = Category::read_all(&app_state.pool).await?;
+    render(&app_state, "poll_form.html", context!{
+        categories => categories,
+        flash => flash,
+    })
+}
+
+async fn create_poll(
+    State(app_state): State<Arc<AppState>>,
+    Form(poll_form): Form<PollForm>,
+) -> impl IntoResponse{
+    if let Err(e) = poll_form.validate(){
+        return failed("/polls/new", e);
+    }
+    let answers = poll_form.answers();
+    let mut new_poll = NewPoll::new(poll_form.category_id, poll_form.question);
+    new_poll.set_priority(poll_form.priority);
+    new_poll.set_publish_at(poll_form.publish_at);
+    match PollWithAnswers::create(&app_state.pool, new_poll, answers).await{
+        Ok(poll) => done("/polls", format!("Poll {} created", poll.get_question())),
+        Err(e) => failed("/polls/new", e),
+    }
+}
+
+async fn edit_poll(
+    State(app_state): State<Arc<AppState>>,
+    Path(poll_id): Path<i64>,
+    Query(flash): Query<Flash>,
+) -> Result<impl IntoResponse, CustomError>{
+    let poll = Poll::read(&app_state.pool, poll_id).await?
+        .ok_or(CustomError::NotFound)?;
+    let answers = Answer::read_for_poll(&app_state.pool, poll_id).await?;
+    let categories = Category::read_all(&app_state.pool).await?;
+    render(&app_state, "poll_form.html", context!{
+        id => poll.get_id(),
+        poll => PollForm::from_poll(&poll, &answers),
+        categories => categories,
+        flash => flash,
+    })
+}
+
+async fn update_poll(
+    State(app_state): State<Arc<AppState>>,
+    Path(poll_id): Path<i64>,
+    Form(poll_form): Form<PollForm>,
+) -> impl IntoResponse{
+    let path = format!("/polls/{}", poll_id);
+    if let Err(e) = poll_form.validate(){
+        return failed(&path, e);
+    }
+    let answers = poll_form.answers();
+    let poll = Poll::new(poll_id, poll_form.category_id, poll_form.question,
+        poll_form.published, poll_form.priority, poll_form.publish_at);
+    match PollWithAnswers::update(&app_state.pool, poll, answers).await{
+        Ok(poll) => done("/polls", format!("Poll {} updated", poll.get_question())),
+        Err(e) => failed(&path, e),
+    }
+}
+
+async fn delete_poll(
+    State(app_state): State<Arc<AppState>>,
+    Path(poll_id): Path<i64>,
+) -> impl IntoResponse{
+    match Poll::delete(&app_state.pool, poll_id).await{
+        Ok(poll) => done("/polls", format!("Poll {} deleted", poll.get_question())),
+        Err(e) => failed("/polls", e),
+    }
+}
+
+/// The items not published yet, by category, in the order of the strategy
+/// of the category, and the ones scheduled for later
+async fn queue(
+    State(app_state): State<Arc<AppState>>,
+    Query(flash): Query<Flash>,
+) -> Result<impl IntoResponse, CustomError>{
+    let mut categories: Vec<Value> = Vec::new();
+    for category in Category::read_all(&app_state.pool).await?{
+        let strategy = category.get_strategy();
+        let mut tips = Tip::read_not_published_in_category(&app_state.pool, category.get_id()).await?;
+        strategy.sort(&mut tips);
+        let mut polls = Poll::read_not_published_in_category(&app_state.pool, category.get_id()).await?;
+        strategy.sort(&mut polls);
+        let scheduled_tips = Tip::read_scheduled_in_category(&app_state.pool, category.get_id()).await?;
+        let scheduled_polls = Poll::read_scheduled_in_category(&app_state.pool, category.get_id()).await?;
+        categories.push(json!({
+            "tips": tips,
+            "polls": polls,
+            "scheduled_tips": scheduled_tips,
+            "scheduled_polls": scheduled_polls,
+            "random": strategy.is_random(),
+            "category": category,
+        }));
+    }
+    render(&app_state, "queue.html", context!{
+        categories => categories,
+        flash => flash,
+    })
+}
+
+async fn publish_tip(
+    State(app_state): State<Arc<AppState>>,
+    Form(publish_form): Form<PublishForm>,
+) -> impl IntoResponse{
+    match publish_next_tip(&app_state, Some(publish_form.category_id)).await{
+        Ok(tip) => done("/queue", format!("Tip {} published", tip.get_title())),
+        Err(e) => failed("/queue", e),
+    }
+}
+
+async fn publish_poll(
+    State(app_state): State<Arc<AppState>>,
+    Form(publish_form): Form<PublishForm>,
+) -> impl IntoResponse{
+    match publish_next_poll(&app_state, Some(publish_form.category_id)).await{
+        Ok(poll) => done("/queue", format!("Poll {} published", poll.get_question())),
+        Err(e) => failed("/queue", e),
+    }
+}
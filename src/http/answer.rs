@@ -0,0 +1,121 @@
+use std::sync::Arc;
+use axum::{
+    Router,
+    Json,
+    extract::{State, Path},
+    routing,
+    response::IntoResponse,
+    http::StatusCode,
+};
+
+use crate::{
+    http::AppState,
+    models::{
+        answer::{
+            Answer,
+            AnswerChanges,
+            NewAnswer,
+            NewPollAnswer,
+        },
+        poll::Poll,
+        validation::{Errors, Validate, MAX_OPTIONS},
+        error::CustomError
+    }
+};
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/api/v1/polls/:id/answers",
+            routing::get(read_all)
+        )
+        .route("/api/v1/polls/:id/answers",
+            routing::post(create)
+        )
+        .route("/api/v1/polls/:id/answers/:answer_id",
+            routing::get(read)
+        )
+        .route("/api/v1/polls/:id/answers/:answer_id",
+            routing::patch(update)
+        )
+        .route("/api/v1/polls/:id/answers/:answer_id",
+            routing::delete(delete)
+        )
+}
+
+/// Gets the answer, only if it belongs to the poll
+async fn read_answer(app_state: &AppState, poll_id: i64, answer_id: i64) -> Result<Answer, CustomError>{
+    match Answer::read(&app_state.pool, answer_id).await?{
+        Some(answer) if answer.get_poll_id() == poll_id => Ok(answer),
+        _ => Err(CustomError::NotFound),
+    }
+}
+
+async fn create(
+    State(app_state): State<Arc<AppState>>,
+    Path(poll_id): Path<i64>,
+    Json(new_answer): Json<NewPollAnswer>,
+) -> Result<impl IntoResponse, CustomError>{
+    Poll::read(&app_state.pool, poll_id).await?
+        .ok_or(CustomError::NotFound)?;
+    new_answer.validate()?;
+    if Answer::read_for_poll(&app_state.pool, poll_id).await?.len() >= MAX_OPTIONS {
+        let mut errors = Errors::default();
+        errors.add("answers", format!("a poll can't have more than {} answers", MAX_OPTIONS));
+        errors.into_result()?;
+    }
+    let answer = NewAnswer::new(poll_id, new_answer.text, new_answer.isok);
+    let answer = match new_answer.position{
+        Some(position) => Answer::create_at(&app_state.pool, answer, position).await?,
+        None => Answer::create(&app_state.pool, answer).await?,
+    };
+    Ok((StatusCode::OK, Json(answer)).into_response())
+}
+
+async fn read(
+    State(app_state): State<Arc<AppState>>,
+    Path((poll_id, answer_id)): Path<(i64, i64)>,
+) -> Result<impl IntoResponse, CustomError>{
+    let answer = read_answer(&app_state, poll_id, answer_id).await?;
+    Ok((StatusCode::OK, Json(answer)).into_response())
+}
+
+async fn read_all(
+    State(app_state): State<Arc<AppState>>,
+    Path(poll_id): Path<i64>,
+) -> Result<impl IntoResponse, CustomError>{
+    Poll::read(&app_state.pool, poll_id).await?
+        .ok_or(CustomError::NotFound)?;
+    let answers = Answer::read_for_poll(&app_state.pool, poll_id).await?;
+    Ok((StatusCode::OK, Json(answers)).into_response())
+}
+
+async fn update(
+    State(app_state): State<Arc<AppState>>,
+    Path((poll_id, answer_id)): Path<(i64, i64)>,
+    Json(changes): Json<AnswerChanges>,
+) -> Result<impl IntoResponse, CustomError>{
+    changes.validate()?;
+    let mut answer = read_answer(&app_state, poll_id, answer_id).await?;
+    if changes.text.is_some() || changes.isok.is_some(){
+        if let Some(text) = changes.text{
+            answer.set_text(text);
+        }
+        if let Some(isok) = changes.isok{
+            answer.set_isok(isok);
+        }
+        answer = Answer::update(&app_state.pool, answer).await?;
+    }
+    if let Some(position) = changes.position{
+        answer = Answer::move_to(&app_state.pool, answer, position).await?;
+    }
+    Ok((StatusCode::OK, Json(answer)).into_response())
+}
+
+async fn delete(
+    State(app_state): State<Arc<AppState>>,
+    Path((poll_id, answer_id)): Path<(i64, i64)>,
+) -> Result<impl IntoResponse, CustomError>{
+    read_answer(&app_state, poll_id, answer_id).await?;
+    let answer = Answer::delete(&app_state.pool, answer_id).await?;
+    Ok((StatusCode::OK, Json(answer)).into_response())
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+use axum::{
+    Router,
+    Json,
+    extract::{State, Path},
+    routing,
+    response::IntoResponse,
+    http::StatusCode,
+};
+
+use crate::{
+    http::AppState,
+    models::{
+        api_key::{
+            ApiKey,
+            NewApiKey,
+        },
+        error::CustomError
+    }
+};
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/api/v1/keys",
+            routing::get(read_all)
+        )
+        .route("/api/v1/keys",
+            routing::post(create)
+        )
+        .route("/api/v1/keys/:id",
+            routing::delete(revoke)
+        )
+}
+
+async fn create(
+    State(app_state): State<Arc<AppState>>,
+    Json(new_api_key): Json<NewApiKey>,
+) -> Result<impl IntoResponse, CustomError>{
+    let api_key = ApiKey::create(&app_state.pool, new_api_key).await?;
+    Ok((StatusCode::OK, Json(api_key)).into_response())
+}
+
+async fn read_all(
+    State(app_state): State<Arc<AppState>>
+) -> Result<impl IntoResponse, CustomError>{
+    let api_keys = ApiKey::read_all(&app_state.pool).await?;
+    Ok((StatusCode::OK, Json(api_keys)).into_response())
+}
+
+async fn revoke(
+    State(app_state): State<Arc<AppState>>,
+    Path(api_key_id): Path<i64>,
+) -> Result<impl IntoResponse, CustomError>{
+    let api_key = ApiKey::revoke(&app_state.pool, api_key_id).await?;
+    Ok((StatusCode::OK, Json(api_key)).into_response())
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+use axum::{
+    extract::State,
+    headers::{authorization::Basic, Authorization},
+    http::{header, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    TypedHeader,
+};
+
+use crate::{
+    http::AppState,
+    models::{
+        api_key::{self, ApiKey},
+        error::CustomError,
+    },
+};
+
+/// Extracts the key from an `Authorization: Bearer <key>` header
+fn bearer<B>(request: &Request<B>) -> Option<&str>{
+    request.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|key| key.trim())
+        .filter(|key| !key.is_empty())
+}
+
+/// Checks the key against the one set in the `API_KEY` environment
+/// variable and the ones stored in the database
+async fn is_valid(app_state: &AppState, key: &str) -> Result<bool, CustomError>{
+    if let Some(master) = &app_state.api_key{
+        if api_key::hash(master) == api_key::hash(key) {
+            tracing::debug!("Authenticated with the master key");
+            return Ok(true);
+        }
+    }
+    match ApiKey::authenticate(&app_state.pool, key).await?{
+        Some(api_key) => {
+            tracing::debug!("Authenticated with key {}", api_key.get_name());
+            Ok(true)
+        },
+        None => Ok(false),
+    }
+}
+
+/// Checks that the request carries a valid API key. The key set in the
+/// `API_KEY` environment variable is always accepted, so the first keys can
+/// be created.
+pub async fn require_api_key<B>(
+    State(app_state): State<Arc<AppState>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, CustomError>{
+    let key = bearer(&request).ok_or(CustomError::Unauthorized)?;
+    if is_valid(&app_state, key).await? {
+        Ok(next.run(request).await)
+    }else{
+        Err(CustomError::Unauthorized)
+    }
+}
+
+/// Asks the browser for credentials for the admin pages. Any user name is
+/// accepted, the password is an API key.
+pub async fn require_login<B>(
+    State(app_state): State<Arc<AppState>>,
+    credentials: Option<TypedHeader<Authorization<Basic>>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, CustomError>{
+    if let Some(TypedHeader(Authorization(basic))) = credentials {
+        if is_valid(&app_state, basic.password()).await? {
+            return Ok(next.run(request).await);
+        }
+    }
+    Ok((
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Basic realm=\"publirs\"")],
+        "Unauthorized",
+    ).into_response())
+}
+
+/// Host and port of an `Origin` or a `Referer`, without the scheme and the
+/// path
+fn authority(url: &str) -> Option<&str>{
+    let (_, rest) = url.split_once("://")?;
+    rest.split('/').next().filter(|authority| !authority.is_empty())
+}
+
+/// Rejects the forms sent to the admin pages from other sites. The browser
+/// sends the credentials with any request to the admin pages, so a page of
+/// another site could make it delete or publish content. The `Origin`, or
+/// the `Referer` when there is none, has to be the host of the request.
+pub async fn require_same_origin<B>(
+    request: Request<B>,
+    next: Next<B>,
+) -> Response{
+    if matches!(*request.method(), Method::GET | Method::HEAD) {
+        return next.run(request).await;
+    }
+    let header = |name| request.headers()
+        .get(name)
+        .and_then(|value: &header::HeaderValue| value.to_str().ok());
+    let source = header(header::ORIGIN)
+        .or_else(|| header(header::REFERER))
+        .and_then(authority);
+    match (source, header(header::HOST)){
+        (Some(source), Some(host)) if source.eq_ignore_ascii_case(host) => next.run(request).await,
+        _ => {
+            tracing::warn!("Rejected a {} to {} from another site", request.method(), request.uri());
+            (StatusCode::FORBIDDEN, "Forbidden").into_response()
+        },
+    }
+}
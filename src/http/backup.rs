@@ -0,0 +1,71 @@
+use std::sync::Arc;
+use axum::{
+    Router,
+    Json,
+    extract::{State, Query},
+    routing,
+    response::IntoResponse,
+    http::{header, HeaderMap, StatusCode},
+};
+use serde::Deserialize;
+
+use crate::{
+    http::{AppState, import::is_yaml},
+    models::{
+        backup::{Backup, Format, OnConflict},
+        error::CustomError,
+    }
+};
+
+#[derive(Debug, Deserialize)]
+struct ExportOptions{
+    #[serde(default)]
+    format: Format,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestoreOptions{
+    #[serde(default)]
+    on_conflict: OnConflict,
+}
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/api/v1/export",
+            routing::get(export)
+        )
+        .route("/api/v1/restore",
+            routing::post(restore)
+        )
+}
+
+async fn export(
+    State(app_state): State<Arc<AppState>>,
+    Query(options): Query<ExportOptions>,
+) -> Result<impl IntoResponse, CustomError>{
+    let backup = Backup::export(&app_state.pool).await?;
+    Ok(match options.format{
+        Format::Json => (StatusCode::OK, Json(backup)).into_response(),
+        Format::Yaml => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "application/yaml")],
+            backup.write(Format::Yaml)?,
+        ).into_response(),
+    })
+}
+
+/// Restores a YAML or a JSON backup, as told by its content type
+async fn restore(
+    State(app_state): State<Arc<AppState>>,
+    Query(options): Query<RestoreOptions>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<impl IntoResponse, CustomError>{
+    let backup = if is_yaml(&headers) {
+        Backup::from_yaml(&body)?
+    }else{
+        Backup::from_json(&body)?
+    };
+    let report = backup.restore(&app_state.pool, options.on_conflict).await?;
+    Ok((StatusCode::OK, Json(report)).into_response())
+}
@@ -2,7 +2,7 @@ use std::sync::Arc;
 use axum::{
     Router,
     Json,
-    extract::{State, Path},
+    extract::{State, Path, Query},
     routing,
     response::IntoResponse,
     http::StatusCode,
@@ -10,10 +10,15 @@ use axum::{
 
 use crate::{
     http::AppState,
-    models::category::{
+    models::{
+        category::{
             Category,
             NewCategory
-        }
+        },
+        listing::{ListParams, Page},
+        validation::Validate,
+        error::CustomError,
+    }
 };
 
 pub fn router() -> Router<Arc<AppState>> {
@@ -33,6 +38,9 @@ pub fn router() -> Router<Arc<AppState>> {
         .route("/api/v1/categories",
             routing::delete(delete)
         )
+        .route("/api/v1/categories/:id/reset",
+            routing::post(reset)
+        )
 }
 
 async fn read(
@@ -45,11 +53,13 @@ async fn read(
     }
 }
 
+/// A page of the categories, filtered and sorted as asked in the query
 async fn read_all(
     State(app_state): State<Arc<AppState>>,
+    Query(params): Query<ListParams>,
 ) -> impl IntoResponse{
-    match Category::read_all(&app_state.pool).await{
-        Ok(categories) => (StatusCode::OK, Json(serde_json::to_value(categories).unwrap())).into_response(),
+    match Category::read_page(&app_state.pool, &params).await{
+        Ok((categories, total)) => (StatusCode::OK, Json(Page::new(categories, total, &params, "/api/v1/categories"))).into_response(),
         Err(e)  => {
             tracing::error!("Error: {:?}", e);
             e.into_response()
@@ -61,6 +71,9 @@ async fn create(
     State(app_state): State<Arc<AppState>>,
     Json(new_channel): Json<NewCategory>,
 ) -> impl IntoResponse{
+    if let Err(e) = new_channel.validate(){
+        return e.into_response();
+    }
     match Category::create(&app_state.pool, new_channel).await{
         Ok(channel) => (StatusCode::OK, Json(channel)).into_response(),
         Err(e) => {
@@ -72,10 +85,17 @@ async fn create(
 
 async fn update(
     State(app_state): State<Arc<AppState>>,
-    Json(channel): Json<Category>,
+    Json(mut channel): Json<Category>,
 ) -> impl IntoResponse{
+    match Category::read(&app_state.pool, channel.get_id()).await{
+        Ok(stored) => channel.keep_secrets(stored),
+        Err(e) => return e.into_response(),
+    }
+    if let Err(e) = channel.validate(){
+        return e.into_response();
+    }
     match Category::update(&app_state.pool, channel).await{
-        Ok(channel) => return(StatusCode::OK, Json(channel)).into_response(),
+        Ok(channel) => (StatusCode::OK, Json(channel)).into_response(),
         Err(e) => {
             tracing::error!("Error: {}", e);
             e.into_response()
@@ -88,7 +108,7 @@ async fn delete(
     Path(channel_id): Path<i64>,
 ) -> impl IntoResponse{
     match Category::delete(&app_state.pool, channel_id).await{
-        Ok(channel) => return(StatusCode::OK, Json(channel)).into_response(),
+        Ok(channel) => (StatusCode::OK, Json(channel)).into_response(),
         Err(e) => {
             tracing::error!("Error: {},", e);
             e.into_response()
@@ -96,3 +116,12 @@ async fn delete(
     }
 }
 
+
+/// Queues again everything the category has published, so a new cycle starts
+async fn reset(
+    State(app_state): State<Arc<AppState>>,
+    Path(category_id): Path<i64>,
+) -> Result<impl IntoResponse, CustomError>{
+    let reset = Category::reset(&app_state.pool, category_id).await?;
+    Ok((StatusCode::OK, Json(reset)).into_response())
+}
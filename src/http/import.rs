@@ -0,0 +1,47 @@
+use std::sync::Arc;
+use axum::{
+    Router,
+    Json,
+    extract::State,
+    routing,
+    response::IntoResponse,
+    http::{header, HeaderMap, StatusCode},
+};
+
+use crate::{
+    http::AppState,
+    models::{
+        import::{self, Document},
+        error::CustomError,
+    }
+};
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/api/v1/import",
+            routing::post(import)
+        )
+}
+
+/// Whether the body is YAML, otherwise it is read as JSON
+pub fn is_yaml(headers: &HeaderMap) -> bool{
+    headers.get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|content_type| content_type.contains("yaml"))
+        .unwrap_or(false)
+}
+
+/// Imports a YAML or a JSON document, as told by its content type
+async fn import(
+    State(app_state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<impl IntoResponse, CustomError>{
+    let document = if is_yaml(&headers) {
+        Document::from_yaml(&body)?
+    }else{
+        Document::from_json(&body)?
+    };
+    let report = import::import(&app_state.pool, document).await?;
+    Ok((StatusCode::OK, Json(report)).into_response())
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+use axum::{
+    Router,
+    Json,
+    extract::{State, Path, Query},
+    routing,
+    response::IntoResponse,
+    http::StatusCode,
+};
+
+use crate::{
+    http::AppState,
+    models::{
+        job::{
+            Job,
+            JobFilter,
+        },
+        error::CustomError
+    }
+};
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/api/v1/jobs",
+            routing::get(search)
+        )
+        .route("/api/v1/jobs/:id/retry",
+            routing::post(retry)
+        )
+        .route("/api/v1/jobs/:id/cancel",
+            routing::post(cancel)
+        )
+}
+
+/// The jobs, the newest first, usually `?state=failed`
+async fn search(
+    State(app_state): State<Arc<AppState>>,
+    Query(filter): Query<JobFilter>,
+) -> Result<impl IntoResponse, CustomError>{
+    let jobs = Job::search(&app_state.pool, &filter).await?;
+    Ok((StatusCode::OK, Json(jobs)).into_response())
+}
+
+/// Puts a failed job back to pending, the worker sends it soon
+async fn retry(
+    State(app_state): State<Arc<AppState>>,
+    Path(job_id): Path<i64>,
+) -> Result<impl IntoResponse, CustomError>{
+    let job = Job::retry(&app_state.pool, job_id).await?;
+    Ok((StatusCode::OK, Json(job)).into_response())
+}
+
+/// Drops a pending or failed job
+async fn cancel(
+    State(app_state): State<Arc<AppState>>,
+    Path(job_id): Path<i64>,
+) -> Result<impl IntoResponse, CustomError>{
+    let job = Job::cancel(&app_state.pool, job_id).await?;
+    Ok((StatusCode::OK, Json(job)).into_response())
+}
@@ -1,35 +1,110 @@
-mod publish;
+pub mod publish;
+mod admin;
+mod answer;
+mod api_key;
+mod auth;
+mod backup;
 mod category;
+mod import;
+mod job;
 mod poll;
+mod publication;
+mod schedule;
+mod search;
+mod template;
 mod tip;
 
-use std::{sync::Arc, net::{SocketAddr, Ipv4Addr}};
-use axum::Server;
+use std::{sync::{Arc, Mutex}, net::{SocketAddr, Ipv4Addr}, path::Path, time::Duration};
+use axum::{Server, middleware};
+use minijinja::{Environment, Source};
+use rand::{SeedableRng, rngs::StdRng};
 use sqlx::SqlitePool;
-use tower_http::trace::TraceLayer;
+use tower_http::{services::ServeDir, trace::TraceLayer};
+
+use crate::{
+    scheduler,
+    worker,
+    models::{
+        publisher::Publishers,
+        strategy::{Candidate, Strategy},
+        telegram::Telegram,
+    },
+};
 
 #[derive(Clone)]
 pub struct AppState {
     pub pool: SqlitePool,
-    pub token: String,
+    pub api_key: Option<String>,
+    pub publishers: Publishers,
+    pub templates: Environment<'static>,
+    /// Used by the random strategies, it can be seeded to make them repeatable
+    pub rng: Arc<Mutex<StdRng>>,
+    /// Time after which a job still claimed is taken as interrupted. Twice
+    /// what the slowest backend can take, as the rate limits can make it
+    /// wait too.
+    pub lease: Duration,
 }
 
 impl AppState {
-    pub fn new(pool: &SqlitePool, token: &str) -> Self{
+    pub fn new(pool: &SqlitePool, telegram: Telegram, api_key: Option<String>, templates: &Path) -> Self{
+        let mut environment = Environment::new();
+        environment.set_source(Source::from_path(templates));
+        let publishers = Publishers::new(telegram);
         Self {
             pool: pool.clone(),
-            token: token.to_string(),
+            api_key,
+            lease: publishers.max_send_time() * 2,
+            publishers,
+            templates: environment,
+            rng: Arc::new(Mutex::new(StdRng::from_entropy())),
         }
     }
+
+    pub fn with_seed(mut self, seed: u64) -> Self{
+        self.rng = Arc::new(Mutex::new(StdRng::seed_from_u64(seed)));
+        self
+    }
+
+    /// Chooses the next item to publish with the strategy
+    pub fn choose<T: Candidate>(&self, strategy: Strategy, items: Vec<T>) -> Option<T>{
+        let mut rng = self.rng.lock().unwrap();
+        strategy.choose(items, &mut *rng)
+    }
 }
 
-pub async fn serve(pool: &SqlitePool, token: &str, port: u16) -> anyhow::Result<()> {
-    let app_state = AppState::new(pool, token);
-    let app = publish::router()
+pub async fn serve(app_state: AppState, resources: &Path, port: u16) -> anyhow::Result<()> {
+    worker::recover(&app_state).await?;
+    let app_state = Arc::new(app_state);
+    scheduler::spawn(app_state.clone());
+    worker::spawn(app_state.clone());
+    let api = publish::router()
+        .merge(answer::router())
+        .merge(api_key::router())
+        .merge(backup::router())
         .merge(category::router())
+        .merge(import::router())
+        .merge(job::router())
         .merge(poll::router())
+        .merge(publication::router())
+        .merge(schedule::router())
+        .merge(search::router())
+        .merge(template::router())
         .merge(tip::router())
-        .with_state(Arc::new(app_state))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::require_api_key
+        ));
+    let admin = admin::router()
+        .route_layer(middleware::from_fn(auth::require_same_origin))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::require_login
+        ));
+    let app = publish::public_router()
+        .merge(api)
+        .merge(admin)
+        .nest_service("/assets", ServeDir::new(resources.join("assets")))
+        .with_state(app_state)
         .layer(TraceLayer::new_for_http());
 
     Server::bind(
@@ -39,4 +114,3 @@ pub async fn serve(pool: &SqlitePool, token: &str, port: u16) -> anyhow::Result<
         .map_err(|_err| anyhow::anyhow!("Can't init")
     )
 }
-
@@ -2,20 +2,34 @@ use std::sync::Arc;
 use axum::{
     Router,
     Json,
-    extract::{State, Path},
+    extract::{State, Path, Query},
     routing,
     response::IntoResponse,
     http::StatusCode,
 };
+use serde::Deserialize;
 
 use crate::{
     http::AppState,
-    models::poll::{
-        Poll,
-        NewPoll,
+    models::{
+        poll::{
+            Poll,
+            NewPoll,
+            PollWithAnswers,
+        },
+        listing::{ListParams, Page},
+        validation::Validate,
+        error::CustomError,
     }
 };
 
+#[derive(Debug, Deserialize)]
+struct ReadOptions{
+    /// Whether to include the answers of the poll, in order
+    #[serde(default)]
+    answers: bool,
+}
+
 pub fn router() -> Router<Arc<AppState>> {
     Router::new()
         .route("/api/v1/polls",
@@ -30,7 +44,10 @@ pub fn router() -> Router<Arc<AppState>> {
         .route("/api/v1/polls",
             routing::put(update)
         )
-        .route("/api/v1/polls",
+        .route("/api/v1/polls/:id",
+            routing::put(replace)
+        )
+        .route("/api/v1/polls/:id",
             routing::delete(delete)
         )
 }
@@ -39,6 +56,9 @@ async fn create(
     State(app_state): State<Arc<AppState>>,
     Json(new_poll): Json<NewPoll>,
 ) -> impl IntoResponse{
+    if let Err(e) = new_poll.validate(){
+        return e.into_response();
+    }
     match Poll::create(&app_state.pool, new_poll).await{
         Ok(poll) => (StatusCode::OK, Json(serde_json::to_value(poll).unwrap())).into_response(),
         Err(e) => {
@@ -51,21 +71,26 @@ async fn create(
 async fn read(
     State(app_state): State<Arc<AppState>>,
     Path(poll_id): Path<i64>,
-) -> impl IntoResponse{
-    match Poll::read(&app_state.pool, poll_id).await{
-        Ok(poll) => (StatusCode::OK, Json(serde_json::to_value(poll).unwrap())).into_response(),
-        Err(e) => {
-            tracing::error!("Error: {}", e);
-            e.into_response()
-        }
-    }
+    Query(options): Query<ReadOptions>,
+) -> Result<impl IntoResponse, CustomError>{
+    let poll = if options.answers {
+        PollWithAnswers::read(&app_state.pool, poll_id).await?
+            .map(|poll| serde_json::to_value(poll).unwrap())
+    }else{
+        Poll::read(&app_state.pool, poll_id).await?
+            .map(|poll| serde_json::to_value(poll).unwrap())
+    };
+    let poll = poll.ok_or(CustomError::NotFound)?;
+    Ok((StatusCode::OK, Json(poll)).into_response())
 }
 
+/// A page of the polls, filtered and sorted as asked in the query
 async fn read_all(
     State(app_state): State<Arc<AppState>>,
+    Query(params): Query<ListParams>,
 ) -> impl IntoResponse{
-    match Poll::read_all(&app_state.pool).await{
-        Ok(polls) => (StatusCode::OK, Json(serde_json::to_value(polls).unwrap())).into_response(),
+    match Poll::read_page(&app_state.pool, &params).await{
+        Ok((polls, total)) => (StatusCode::OK, Json(Page::new(polls, total, &params, "/api/v1/polls"))).into_response(),
         Err(e)  => {
             tracing::error!("Error: {}", e);
             e.into_response()
@@ -77,8 +102,11 @@ async fn update(
     State(app_state): State<Arc<AppState>>,
     Json(channel): Json<Poll>,
 ) -> impl IntoResponse{
+    if let Err(e) = channel.validate(){
+        return e.into_response();
+    }
     match Poll::update(&app_state.pool, channel).await{
-        Ok(channel) => return(StatusCode::OK, Json(channel)).into_response(),
+        Ok(channel) => (StatusCode::OK, Json(channel)).into_response(),
         Err(e)  => {
             tracing::error!("Error: {}", e);
             e.into_response()
@@ -86,12 +114,28 @@ async fn update(
     }
 }
 
+/// Replaces the poll and all its answers, which are stored in the given order
+async fn replace(
+    State(app_state): State<Arc<AppState>>,
+    Path(poll_id): Path<i64>,
+    Json(poll): Json<PollWithAnswers>,
+) -> Result<impl IntoResponse, CustomError>{
+    if poll.get_id() != poll_id {
+        return Err(CustomError::BadRequest);
+    }
+    poll.validate()?;
+    let (poll, answers) = poll.into_parts();
+    let poll = PollWithAnswers::update(&app_state.pool, poll, answers).await?;
+    Ok((StatusCode::OK, Json(poll)).into_response())
+}
+
+/// Deletes the poll together with its answers
 async fn delete(
     State(app_state): State<Arc<AppState>>,
     Path(channel_id): Path<i64>,
 ) -> impl IntoResponse{
     match Poll::delete(&app_state.pool, channel_id).await{
-        Ok(channel) => return(StatusCode::OK, Json(channel)).into_response(),
+        Ok(channel) => (StatusCode::OK, Json(channel)).into_response(),
         Err(e)  => {
             tracing::error!("Error: {}", e);
             e.into_response()
@@ -0,0 +1,35 @@
+use std::sync::Arc;
+use axum::{
+    Router,
+    Json,
+    extract::{State, Query},
+    routing,
+    response::IntoResponse,
+    http::StatusCode,
+};
+
+use crate::{
+    http::AppState,
+    models::{
+        publication::{
+            Publication,
+            PublicationFilter,
+        },
+        error::CustomError
+    }
+};
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/api/v1/publications",
+            routing::get(search)
+        )
+}
+
+async fn search(
+    State(app_state): State<Arc<AppState>>,
+    Query(filter): Query<PublicationFilter>,
+) -> Result<impl IntoResponse, CustomError>{
+    let publications = Publication::search(&app_state.pool, &filter).await?;
+    Ok((StatusCode::OK, Json(publications)).into_response())
+}
@@ -4,10 +4,12 @@ use axum::{
     Router,
     Json,
     routing,
-    extract::State,
+    extract::{State, Path, Query},
     http::StatusCode,
     response::IntoResponse
 };
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
 use tracing::debug;
 
 use crate::models::{
@@ -23,22 +25,30 @@ use crate::models::{
         NewPollWithAnswers,
         PollWithAnswers
     },
-    answer::{
-        Answer,
-        NewAnswer
-    },
-    telegram::Telegram,
+    answer::Answer,
+    job::Job,
+    kind::Kind,
+    markup,
+    publication::Publication,
+    rotation::Rotation,
+    publisher::{Backend, Message, Quiz, Receipt},
+    template::Template,
+    validation::{Errors, FieldError, Validate, MAX_MESSAGE_LENGTH, MAX_QUESTION_LENGTH},
     error::CustomError,
 };
 
 use super::AppState;
-use tracing;
 
-pub fn router() -> Router<Arc<AppState>>{
+/// Routes that don't require authentication
+pub fn public_router() -> Router<Arc<AppState>>{
     Router::new()
         .route("/api/v1/status",
             routing::get(get_status)
         )
+}
+
+pub fn router() -> Router<Arc<AppState>>{
+    Router::new()
         .route("/api/v1/publish_poll",
             routing::get(publish_poll)
         )
@@ -51,6 +61,12 @@ pub fn router() -> Router<Arc<AppState>>{
         .route("/api/v1/create_tip",
             routing::post(create_tip)
         )
+        .route("/api/v1/tips/:id/publish",
+            routing::post(publish_tip_by_id)
+        )
+        .route("/api/v1/polls/:id/publish",
+            routing::post(publish_poll_by_id)
+        )
 }
 
 async fn get_status() -> impl IntoResponse{
@@ -63,35 +79,253 @@ async fn get_status() -> impl IntoResponse{
     (StatusCode::OK, Json(msg)).into_response()
 }
 
+/// How to choose the category of the next item
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Mode{
+    /// The first item not published, of any category
+    #[default]
+    First,
+    /// Each time from the next category with items not published, or any
+    /// category that recycles when there are none
+    Next,
+}
+
+#[derive(Debug, Deserialize)]
+struct NextOptions{
+    /// Name of the category to publish from
+    category: Option<String>,
+    #[serde(default)]
+    mode: Mode,
+}
+
+/// The category to publish from, if any
+async fn choose_category(app_state: &AppState, kind: Kind, options: &NextOptions) -> Result<Option<i64>, CustomError>{
+    match (&options.category, options.mode){
+        (Some(_), Mode::Next) => Err(CustomError::Validation(vec![FieldError{
+            field: "category".to_string(),
+            message: "can't be used with the mode next".to_string(),
+        }])),
+        (Some(name), Mode::First) => Ok(Some(Category::search(&app_state.pool, name).await?.get_id())),
+        // With nothing new in any category, the item to recycle is looked
+        // for in all of them
+        (None, Mode::Next) => Rotation::next(&app_state.pool, kind).await,
+        (None, Mode::First) => Ok(None),
+    }
+}
+
 async fn publish_tip(
     State(app_state): State<Arc<AppState>>,
+    Query(options): Query<NextOptions>,
 ) -> Result<impl IntoResponse, CustomError>{
-    match Tip::read_not_published(&app_state.pool).await?{
-        Some(mut tip) => {
-            debug!("Tip: {:?}", tip);
-            let category = Category::read(&app_state.pool, tip.get_category_id()).await?;
-            let message = format!(
-                "<i>Tip</i>: <b>{}</b>\n\n{}\n\n#{}",
-                tip.get_title(),
-                tip.get_text(),
-                category.get_name());
-            let telegram = Telegram::new(&app_state.token);
-            telegram.send_message(
-                category.get_chat_id(),
-                category.get_thread_id(),
-                &message
-            ).await?;
-            tracing::info!("Send tip");
-            tip.set_published(true);
-            tracing::debug!("Tip: {:?}", tip);
-            Tip::update(&app_state.pool, tip).await?;
-            Ok(StatusCode::OK)
+    let category_id = choose_category(&app_state, Kind::Tip, &options).await?;
+    publish_next_tip(&app_state, category_id).await?;
+    Ok(StatusCode::OK)
+}
+
+/// Publishes a tip not yet published of the category, or of the category
+/// of the oldest one, chosen with the strategy of the category, and marks it
+/// as published. When there is none, a tip published before is published
+/// again if its category recycles.
+pub async fn publish_next_tip(app_state: &AppState, category_id: Option<i64>) -> Result<Tip, CustomError>{
+    let tip = match next_tip(app_state, category_id).await?{
+        Some(tip) => tip,
+        None => match Tip::read_recyclable(&app_state.pool, category_id).await?{
+            Some(tip) => {
+                tracing::info!("Recycle tip {}", tip.get_id());
+                tip
+            },
+            None => {
+                tracing::info!("Not new tips");
+                return Err(CustomError::NotFound);
+            },
+        },
+    };
+    debug!("Tip: {:?}", tip);
+    let prepared = prepare_tip(app_state, tip).await?;
+    deliver_tip(app_state, prepared).await
+}
+
+/// The tip not yet published that goes next, if any is due
+async fn next_tip(app_state: &AppState, category_id: Option<i64>) -> Result<Option<Tip>, CustomError>{
+    let category_id = match category_id{
+        Some(category_id) => category_id,
+        None => match Tip::read_not_published(&app_state.pool).await?{
+            Some(tip) => tip.get_category_id(),
+            None => return Ok(None),
         },
-        None => {
-            tracing::info!("Not new tips");
-            Err(CustomError::NotFound)
+    };
+    let category = Category::read(&app_state.pool, category_id).await?;
+    let tips = Tip::read_not_published_in_category(&app_state.pool, category_id).await?;
+    Ok(app_state.choose(category.get_strategy(), tips))
+}
+
+/// A tip rendered for its category, ready to be sent
+pub struct PreparedTip{
+    tip: Tip,
+    category: Category,
+    message: Message,
+}
+
+/// A poll rendered for its category, ready to be sent
+pub struct PreparedPoll{
+    poll: Poll,
+    category: Category,
+    quiz: Quiz,
+}
+
+/// What would be sent and where, without sending it. The payload is what
+/// the publisher of the category would send to its backend.
+#[derive(Debug, Serialize)]
+pub struct Preview{
+    kind: Kind,
+    id: i64,
+    backend: Backend,
+    chat_id: String,
+    thread_id: i64,
+    payload: Value,
+}
+
+impl Preview{
+    fn new(kind: Kind, id: i64, category: &Category, payload: Value) -> Self{
+        Self{
+            kind,
+            id,
+            backend: category.get_backend(),
+            chat_id: category.get_chat_id().to_string(),
+            thread_id: category.get_thread_id(),
+            payload,
         }
+    }
+}
+
+impl PreparedTip{
+    pub fn preview(&self, app_state: &AppState) -> Result<Preview, CustomError>{
+        let publisher = app_state.publishers.get(self.category.get_backend())?;
+        let payload = publisher.preview_text(&self.category, &self.message)?;
+        Ok(Preview::new(Kind::Tip, self.tip.get_id(), &self.category, payload))
+    }
+}
 
+impl PreparedPoll{
+    pub fn preview(&self, app_state: &AppState) -> Result<Preview, CustomError>{
+        let publisher = app_state.publishers.get(self.category.get_backend())?;
+        let payload = publisher.preview_quiz(&self.category, &self.quiz)?;
+        Ok(Preview::new(Kind::Poll, self.poll.get_id(), &self.category, payload))
+    }
+}
+
+/// Renders the tip with the template of its category and checks it fits in
+/// a message
+pub async fn prepare_tip(app_state: &AppState, tip: Tip) -> Result<PreparedTip, CustomError>{
+    let category = Category::read(&app_state.pool, tip.get_category_id()).await?;
+    let html = Template::render_tip(&app_state.pool, &tip, &category).await?;
+    // The template adds its own text to the title and the text of the tip
+    let mut errors = Errors::default();
+    if markup::html_to_text(&html).chars().count() > MAX_MESSAGE_LENGTH {
+        errors.add("text", format!("the message rendered with the template can't be longer than {} characters",
+            MAX_MESSAGE_LENGTH));
+    }
+    errors.into_result()?;
+    let message = Message{
+        title: tip.get_title().to_string(),
+        text: tip.get_text().to_string(),
+        html,
+    };
+    Ok(PreparedTip{
+        tip,
+        category,
+        message,
+    })
+}
+
+/// Sends the tip with a job, so it can't be sent twice at the same time,
+/// keeps the result in the history and marks it as published
+pub async fn deliver_tip(app_state: &AppState, prepared: PreparedTip) -> Result<Tip, CustomError>{
+    let job = Job::start(&app_state.pool, Kind::Tip, prepared.tip.get_id(),
+        prepared.category.get_id()).await?;
+    send_tip(app_state, &job, prepared).await
+}
+
+/// Sends the tip of the claimed job. The job is marked as sent in the
+/// same transaction that marks the tip as published.
+async fn send_tip(app_state: &AppState, job: &Job, prepared: PreparedTip) -> Result<Tip, CustomError>{
+    let PreparedTip{tip, category, message} = prepared;
+    let publisher = match app_state.publishers.get(category.get_backend()){
+        Ok(publisher) => publisher,
+        Err(e) => return Err(fail(app_state, job, e).await),
+    };
+    let result = publisher.send_text(&category, &message).await;
+    record(app_state, Kind::Tip, tip.get_id(), &category, &result).await;
+    let receipt = match result{
+        Ok(receipt) => receipt,
+        Err(e) => return Err(fail(app_state, job, e).await),
+    };
+    tracing::info!("Send tip");
+    match finish_tip(app_state, job, &receipt).await{
+        Ok(tip) => Ok(tip),
+        Err(e) => Err(fail(app_state, job, CustomError::ServerError(format!(
+            "Sent, but it couldn't be marked as published: {}", e))).await),
+    }
+}
+
+async fn finish_tip(app_state: &AppState, job: &Job, receipt: &Receipt) -> Result<Tip, CustomError>{
+    let mut tx = app_state.pool.begin()
+        .await
+        .map_err(|e| CustomError::ServerError(e.to_string()))?;
+    Job::finish(&mut tx, job.get_id(), receipt).await?;
+    let tip = Tip::mark_published(&mut tx, job.get_item_id()).await?;
+    Rotation::advance(&mut tx, Kind::Tip, tip.get_category_id()).await?;
+    tx.commit()
+        .await
+        .map_err(|e| CustomError::ServerError(e.to_string()))?;
+    Ok(tip)
+}
+
+/// Marks the job as failed, to be retried or cancelled, and gives back the
+/// error. A failure here is only logged, the job is failed when the server
+/// starts again.
+async fn fail(app_state: &AppState, job: &Job, error: CustomError) -> CustomError{
+    if let Err(e) = Job::fail(&app_state.pool, job.get_id(), &error.to_string()).await{
+        tracing::error!("Can't mark the job {} as failed: {}", job.get_id(), e);
+    }
+    error
+}
+
+/// Sends the item of a claimed job, as it is now
+pub async fn run_job(app_state: &AppState, job: &Job) -> Result<(), CustomError>{
+    match job.get_kind(){
+        Kind::Tip => {
+            let prepared = match Tip::read(&app_state.pool, job.get_item_id()).await{
+                Ok(Some(tip)) => prepare_tip(app_state, tip).await,
+                Ok(None) => Err(CustomError::NotFound),
+                Err(e) => Err(e),
+            };
+            match prepared{
+                Ok(prepared) => send_tip(app_state, job, prepared).await.map(|_| ()),
+                Err(e) => Err(fail(app_state, job, e).await),
+            }
+        },
+        Kind::Poll => {
+            let prepared = match Poll::read(&app_state.pool, job.get_item_id()).await{
+                Ok(Some(poll)) => prepare_poll(app_state, poll).await,
+                Ok(None) => Err(CustomError::NotFound),
+                Err(e) => Err(e),
+            };
+            match prepared{
+                Ok(prepared) => send_poll(app_state, job, prepared).await.map(|_| ()),
+                Err(e) => Err(fail(app_state, job, e).await),
+            }
+        },
+    }
+}
+
+/// Keeps the result of the publication in the history. A failure here is
+/// only logged, what has been sent can't be undone.
+async fn record(app_state: &AppState, kind: Kind, item_id: i64, category: &Category,
+        result: &Result<Receipt, CustomError>){
+    if let Err(e) = Publication::create(&app_state.pool, kind, item_id, category, result).await{
+        tracing::error!("Can't record the publication of {} {}: {}", kind, item_id, e);
     }
 }
 
@@ -99,75 +333,362 @@ async fn create_tip(
     State(app_state): State<Arc<AppState>>,
     Json(new_tip): Json<NewTipWithCategory>,
 ) -> Result<impl IntoResponse, CustomError>{
+    new_tip.validate()?;
     let category = Category::search(
         &app_state.pool,
         &new_tip.category)
         .await?;
-    let new_tip = NewTip::new(category.get_id(), new_tip.title, new_tip.text);
-    let tip = Tip::create(&app_state.pool, new_tip).await?;
+    let mut tip = NewTip::new(category.get_id(), new_tip.title, new_tip.text);
+    tip.set_priority(new_tip.priority);
+    tip.set_publish_at(new_tip.publish_at);
+    let tip = Tip::create(&app_state.pool, tip).await?;
     Ok((StatusCode::OK, Json(tip)).into_response())
 }
 
 async fn publish_poll(
     State(app_state): State<Arc<AppState>>,
+    Query(options): Query<NextOptions>,
 ) -> Result<impl IntoResponse, CustomError>{
-    match Poll::read_not_published(&app_state.pool).await?{
-        Some(mut poll) => {
-            debug!("Poll: {:?}", poll);
-            let category = Category::read(&app_state.pool, poll.get_category_id()).await?;
-            let answers = Answer::read_for_poll(&app_state.pool, poll.get_id()).await?;
-            tracing::debug!("Answers: {:?}", answers);
-            if answers.len() == 0 {
+    let category_id = choose_category(&app_state, Kind::Poll, &options).await?;
+    publish_next_poll(&app_state, category_id).await?;
+    Ok(StatusCode::OK)
+}
+
+/// Publishes a poll not yet published of the category, or of the category
+/// of the oldest one, chosen with the strategy of the category, and marks it
+/// as published. When there is none, a poll published before is published
+/// again if its category recycles.
+pub async fn publish_next_poll(app_state: &AppState, category_id: Option<i64>) -> Result<Poll, CustomError>{
+    let poll = match next_poll(app_state, category_id).await?{
+        Some(poll) => poll,
+        None => match Poll::read_recyclable(&app_state.pool, category_id).await?{
+            Some(poll) => {
+                tracing::info!("Recycle poll {}", poll.get_id());
+                poll
+            },
+            None => {
+                tracing::info!("Not new polls");
                 return Err(CustomError::NotFound);
-            }
-            let telegram = Telegram::new(&app_state.token);
-            let options = answers.iter().map(|x| x.get_text()).collect();
-
-            let correct_option_id: i64 = answers.iter().position(|x| x.get_isok() == true).unwrap().try_into().unwrap();
-            let question = format!("{}\n#{}", poll.get_question(), category.get_name());
-            telegram.send_poll(
-                category.get_chat_id(),
-                category.get_thread_id(),
-                &question,
-                options,
-                correct_option_id
-            ).await?;
-            tracing::info!("Send poll");
-            poll.set_published(true);
-            tracing::debug!("Tip: {:?}", poll);
-            Poll::update(&app_state.pool, poll).await?;
-            Ok(StatusCode::OK)
+            },
         },
-        None => {
-            tracing::info!("Not new polls");
-            Err(CustomError::NotFound)
-        }
+    };
+    debug!("Poll: {:?}", poll);
+    let prepared = prepare_poll(app_state, poll).await?;
+    deliver_poll(app_state, prepared).await
+}
+
+/// The poll not yet published that goes next, if any is due
+async fn next_poll(app_state: &AppState, category_id: Option<i64>) -> Result<Option<Poll>, CustomError>{
+    let category_id = match category_id{
+        Some(category_id) => category_id,
+        None => match Poll::read_not_published(&app_state.pool).await?{
+            Some(poll) => poll.get_category_id(),
+            None => return Ok(None),
+        },
+    };
+    let category = Category::read(&app_state.pool, category_id).await?;
+    let polls = Poll::read_not_published_in_category(&app_state.pool, category_id).await?;
+    Ok(app_state.choose(category.get_strategy(), polls))
+}
+
+/// Renders the poll with the template of its category and checks it can be
+/// sent as a quiz
+pub async fn prepare_poll(app_state: &AppState, poll: Poll) -> Result<PreparedPoll, CustomError>{
+    let category = Category::read(&app_state.pool, poll.get_category_id()).await?;
+    let answers = Answer::read_for_poll(&app_state.pool, poll.get_id()).await?;
+    tracing::debug!("Answers: {:?}", answers);
+    // The poll may have been stored before it was validated or
+    // changed an answer at a time
+    let mut errors = Errors::default();
+    let correct_option_id = errors.answers(answers.iter()
+        .map(|answer| (answer.get_text(), answer.get_isok())));
+    let question = Template::render_poll(&app_state.pool, &poll, &answers, &category).await?;
+    errors.text("question", &question, MAX_QUESTION_LENGTH);
+    errors.into_result()?;
+    let quiz = Quiz{
+        question,
+        options: answers.iter().map(|x| x.get_text().to_string()).collect(),
+        correct_option_id: correct_option_id.unwrap_or_default(),
+    };
+    Ok(PreparedPoll{
+        poll,
+        category,
+        quiz,
+    })
+}
+
+/// Sends the poll with a job, so it can't be sent twice at the same time,
+/// keeps the result in the history and marks it as published
+pub async fn deliver_poll(app_state: &AppState, prepared: PreparedPoll) -> Result<Poll, CustomError>{
+    let job = Job::start(&app_state.pool, Kind::Poll, prepared.poll.get_id(),
+        prepared.category.get_id()).await?;
+    send_poll(app_state, &job, prepared).await
+}
+
+/// Sends the poll of the claimed job. The job is marked as sent in the
+/// same transaction that marks the poll as published.
+async fn send_poll(app_state: &AppState, job: &Job, prepared: PreparedPoll) -> Result<Poll, CustomError>{
+    let PreparedPoll{poll, category, quiz} = prepared;
+    let publisher = match app_state.publishers.get(category.get_backend()){
+        Ok(publisher) => publisher,
+        Err(e) => return Err(fail(app_state, job, e).await),
+    };
+    let result = publisher.send_quiz(&category, &quiz).await;
+    record(app_state, Kind::Poll, poll.get_id(), &category, &result).await;
+    let receipt = match result{
+        Ok(receipt) => receipt,
+        Err(e) => return Err(fail(app_state, job, e).await),
+    };
+    tracing::info!("Send poll");
+    match finish_poll(app_state, job, &receipt).await{
+        Ok(poll) => Ok(poll),
+        Err(e) => Err(fail(app_state, job, CustomError::ServerError(format!(
+            "Sent, but it couldn't be marked as published: {}", e))).await),
+    }
+}
+
+async fn finish_poll(app_state: &AppState, job: &Job, receipt: &Receipt) -> Result<Poll, CustomError>{
+    let mut tx = app_state.pool.begin()
+        .await
+        .map_err(|e| CustomError::ServerError(e.to_string()))?;
+    Job::finish(&mut tx, job.get_id(), receipt).await?;
+    let poll = Poll::mark_published(&mut tx, job.get_item_id()).await?;
+    Rotation::advance(&mut tx, Kind::Poll, poll.get_category_id()).await?;
+    tx.commit()
+        .await
+        .map_err(|e| CustomError::ServerError(e.to_string()))?;
+    Ok(poll)
+}
+
+#[derive(Debug, Deserialize)]
+struct PublishOptions{
+    /// Only show what would be sent
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Publishes the tip, even if it has been published before
+async fn publish_tip_by_id(
+    State(app_state): State<Arc<AppState>>,
+    Path(tip_id): Path<i64>,
+    Query(options): Query<PublishOptions>,
+) -> Result<impl IntoResponse, CustomError>{
+    let tip = Tip::read(&app_state.pool, tip_id).await?
+        .ok_or(CustomError::NotFound)?;
+    let prepared = prepare_tip(&app_state, tip).await?;
+    if options.dry_run {
+        return Ok((StatusCode::OK, Json(prepared.preview(&app_state)?)).into_response());
     }
+    let tip = deliver_tip(&app_state, prepared).await?;
+    Ok((StatusCode::OK, Json(tip)).into_response())
+}
+
+/// Publishes the poll, even if it has been published before
+async fn publish_poll_by_id(
+    State(app_state): State<Arc<AppState>>,
+    Path(poll_id): Path<i64>,
+    Query(options): Query<PublishOptions>,
+) -> Result<impl IntoResponse, CustomError>{
+    let poll = Poll::read(&app_state.pool, poll_id).await?
+        .ok_or(CustomError::NotFound)?;
+    let prepared = prepare_poll(&app_state, poll).await?;
+    if options.dry_run {
+        return Ok((StatusCode::OK, Json(prepared.preview(&app_state)?)).into_response());
+    }
+    let poll = deliver_poll(&app_state, prepared).await?;
+    Ok((StatusCode::OK, Json(poll)).into_response())
 }
 
 async fn create_poll(
     State(app_state): State<Arc<AppState>>,
     Json(new_pollwa): Json<NewPollWithAnswers>,
 ) -> Result<impl IntoResponse, CustomError>{
+    new_pollwa.validate()?;
     let category = Category::search(
         &app_state.pool,
         &new_pollwa.category)
         .await?;
-    let new_poll =  NewPoll::new(category.get_id(), new_pollwa.question);
-    let poll = Poll::create( &app_state.pool, new_poll).await?;
-    let mut answers = Vec::new();
-    for item in new_pollwa.answers{
-        let new_answer = NewAnswer::new(poll.get_id(), item.text, item.isok);
-        let answer = Answer::create(&app_state.pool, new_answer).await?;
-        answers.push(answer);
-    }
-    let pwa = PollWithAnswers::new(
-        poll.get_id(),
-        poll.get_category_id(),
-        poll.get_question().to_string(),
-        poll.get_published(),
-        answers,
-    );
+    let mut new_poll = NewPoll::new(category.get_id(), new_pollwa.question);
+    new_poll.set_priority(new_pollwa.priority);
+    new_poll.set_publish_at(new_pollwa.publish_at);
+    let pwa = PollWithAnswers::create(&app_state.pool, new_poll, new_pollwa.answers).await?;
     Ok((StatusCode::OK, Json(pwa)).into_response())
 }
 
+
+#[cfg(test)]
+mod tests{
+    use std::path::Path;
+
+    use crate::{
+        database,
+        models::{
+            answer::NewBasicAnswer,
+            category::NewCategory,
+            publisher::{RecordingPublisher, Sent},
+            telegram::Telegram,
+        },
+    };
+    use super::*;
+
+    async fn setup() -> (AppState, Arc<RecordingPublisher>){
+        let pool = database::memory().await;
+        let resources = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let recorder = Arc::new(RecordingPublisher::new());
+        let mut app_state = AppState::new(&pool, Telegram::new("token", "http://127.0.0.1:1"),
+            None, &resources.join("templates"));
+        app_state.publishers = app_state.publishers.with(Backend::Telegram, recorder.clone());
+        Category::create(&pool, NewCategory::new("Rust".to_string(), "@rust".to_string(),
+            0, Backend::Telegram)).await.unwrap();
+        (app_state, recorder)
+    }
+
+    #[tokio::test]
+    async fn publishes_the_next_tip_through_the_publisher(){
+        let (app_state, recorder) = setup().await;
+        let new_tip = NewTip::new(1, "Ownership".to_string(), "Each value has an owner".to_string());
+        let tip = Tip::create(&app_state.pool, new_tip).await.unwrap();
+
+        let published = publish_next_tip(&app_state, None).await.unwrap();
+
+        assert_eq!(published.get_id(), tip.get_id());
+        match recorder.sent().as_slice(){
+            [Sent::Text{category_id, message}] => {
+                assert_eq!(*category_id, 1);
+                assert_eq!(message.title, "Ownership");
+                assert_eq!(message.text, "Each value has an owner");
+                assert!(message.html.contains("<b>Ownership</b>"));
+                assert!(message.html.ends_with("#Rust"));
+            },
+            sent => panic!("Unexpected messages: {:?}", sent),
+        }
+        assert!(matches!(publish_next_tip(&app_state, None).await, Err(CustomError::NotFound)));
+        assert_eq!(recorder.sent().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn publishes_the_next_poll_through_the_publisher(){
+        let (app_state, recorder) = setup().await;
+        let answers = vec![
+            NewBasicAnswer{text: "Box".to_string(), isok: false},
+            NewBasicAnswer{text: "Rc".to_string(), isok: true},
+        ];
+        PollWithAnswers::create(&app_state.pool,
+            NewPoll::new(1, "Shared ownership?".to_string()), answers).await.unwrap();
+
+        publish_next_poll(&app_state, Some(1)).await.unwrap();
+
+        assert_eq!(recorder.sent(), vec![Sent::Quiz{
+            category_id: 1,
+            quiz: Quiz{
+                question: "Shared ownership?\n#Rust".to_string(),
+                options: vec!["Box".to_string(), "Rc".to_string()],
+                correct_option_id: 1,
+            },
+        }]);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_tip_too_long_once_rendered(){
+        let (app_state, recorder) = setup().await;
+        let new_tip = NewTip::new(1, "Ownership".to_string(), "a".repeat(MAX_MESSAGE_LENGTH - 9));
+        new_tip.validate().unwrap();
+        Tip::create(&app_state.pool, new_tip).await.unwrap();
+
+        let result = publish_next_tip(&app_state, None).await;
+
+        assert!(matches!(result, Err(CustomError::Validation(_))), "{:?}", result);
+        assert!(recorder.sent().is_empty());
+    }
+
+
+    #[tokio::test]
+    async fn rotation_recycles_when_nothing_is_new(){
+        let (app_state, recorder) = setup().await;
+        let category: NewCategory = serde_json::from_value(serde_json::json!({
+            "name": "Go",
+            "chat_id": "@go",
+            "thread_id": 0,
+            "recycle": true,
+            "cooldown": 0,
+        })).unwrap();
+        Category::create(&app_state.pool, category).await.unwrap();
+        let tip = Tip::create(&app_state.pool,
+            NewTip::new(2, "Goroutines".to_string(), "Cheap threads".to_string())).await.unwrap();
+        let next = NextOptions{
+            category: None,
+            mode: Mode::Next,
+        };
+
+        let category_id = choose_category(&app_state, Kind::Tip, &next).await.unwrap();
+        assert_eq!(category_id, Some(2));
+        publish_next_tip(&app_state, category_id).await.unwrap();
+
+        let category_id = choose_category(&app_state, Kind::Tip, &next).await.unwrap();
+        assert_eq!(category_id, None);
+        let recycled = publish_next_tip(&app_state, category_id).await.unwrap();
+        assert_eq!(recycled.get_id(), tip.get_id());
+        assert_eq!(recorder.sent().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rotation_keeps_the_turn_of_a_category_that_fails(){
+        let (app_state, recorder) = setup().await;
+        // Without a webhook the send fails
+        Category::create(&app_state.pool, NewCategory::new("Go".to_string(), "".to_string(),
+            0, Backend::Discord)).await.unwrap();
+        for category_id in [1, 1, 2]{
+            Tip::create(&app_state.pool, NewTip::new(category_id, format!("Tip {}", category_id),
+                "Text".to_string())).await.unwrap();
+        }
+        let next = NextOptions{
+            category: None,
+            mode: Mode::Next,
+        };
+
+        let category_id = choose_category(&app_state, Kind::Tip, &next).await.unwrap();
+        assert_eq!(category_id, Some(1));
+        publish_next_tip(&app_state, category_id).await.unwrap();
+        let category_id = choose_category(&app_state, Kind::Tip, &next).await.unwrap();
+        assert_eq!(category_id, Some(2));
+        assert!(publish_next_tip(&app_state, category_id).await.is_err());
+
+        let last: i64 = sqlx::query_scalar("SELECT category_id FROM rotations WHERE kind = 'tip'")
+            .fetch_one(&app_state.pool)
+            .await
+            .unwrap();
+        assert_eq!(last, 1);
+        assert_eq!(recorder.sent().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn previews_what_the_backend_of_the_category_would_send(){
+        let (app_state, recorder) = setup().await;
+        Category::create(&app_state.pool, NewCategory::new("Go".to_string(), "".to_string(),
+            0, Backend::Discord)).await.unwrap();
+        Category::create(&app_state.pool, NewCategory::new("Zig".to_string(), "".to_string(),
+            0, Backend::Mastodon)).await.unwrap();
+        let tip = Tip::create(&app_state.pool,
+            NewTip::new(2, "Goroutines".to_string(), "Cheap threads".to_string())).await.unwrap();
+        let answers = vec![
+            NewBasicAnswer{text: "comptime".to_string(), isok: true},
+            NewBasicAnswer{text: "macros".to_string(), isok: false},
+        ];
+        let poll = PollWithAnswers::create(&app_state.pool,
+            NewPoll::new(3, "Generics?".to_string()), answers).await.unwrap();
+
+        let preview = prepare_tip(&app_state, tip).await.unwrap().preview(&app_state).unwrap();
+        let preview = serde_json::to_value(preview).unwrap();
+        assert_eq!(preview["backend"], "discord");
+        assert_eq!(preview["payload"]["embeds"][0]["title"], "Goroutines");
+        assert_eq!(preview["payload"]["embeds"][0]["footer"]["text"], "#Go");
+
+        let poll = Poll::read(&app_state.pool, poll.get_id()).await.unwrap().unwrap();
+        let preview = prepare_poll(&app_state, poll).await.unwrap().preview(&app_state).unwrap();
+        let preview = serde_json::to_value(preview).unwrap();
+        assert_eq!(preview["backend"], "mastodon");
+        assert_eq!(preview["payload"]["status"], "Generics?\n#Zig");
+        assert_eq!(preview["payload"]["poll"]["options"], serde_json::json!(["comptime", "macros"]));
+        assert!(recorder.sent().is_empty());
+    }
+}
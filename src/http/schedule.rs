@@ -0,0 +1,81 @@
+use std::sync::Arc;
+use axum::{
+    Router,
+    Json,
+    extract::{State, Path},
+    routing,
+    response::IntoResponse,
+    http::StatusCode,
+};
+
+use crate::{
+    http::AppState,
+    models::{
+        category::Category,
+        schedule::{
+            Schedule,
+            NewSchedule,
+        },
+        error::CustomError
+    }
+};
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/api/v1/schedules",
+            routing::get(read_all)
+        )
+        .route("/api/v1/schedules",
+            routing::post(create)
+        )
+        .route("/api/v1/schedules",
+            routing::put(update)
+        )
+        .route("/api/v1/schedules/:id",
+            routing::get(read)
+        )
+        .route("/api/v1/schedules/:id",
+            routing::delete(delete)
+        )
+}
+
+async fn create(
+    State(app_state): State<Arc<AppState>>,
+    Json(new_schedule): Json<NewSchedule>,
+) -> Result<impl IntoResponse, CustomError>{
+    Category::read(&app_state.pool, new_schedule.get_category_id()).await?;
+    let schedule = Schedule::create(&app_state.pool, new_schedule).await?;
+    Ok((StatusCode::OK, Json(schedule)).into_response())
+}
+
+async fn read(
+    State(app_state): State<Arc<AppState>>,
+    Path(schedule_id): Path<i64>,
+) -> Result<impl IntoResponse, CustomError>{
+    let schedule = Schedule::read(&app_state.pool, schedule_id).await?;
+    Ok((StatusCode::OK, Json(schedule)).into_response())
+}
+
+async fn read_all(
+    State(app_state): State<Arc<AppState>>
+) -> Result<impl IntoResponse, CustomError>{
+    let schedules = Schedule::read_all(&app_state.pool).await?;
+    Ok((StatusCode::OK, Json(schedules)).into_response())
+}
+
+async fn update(
+    State(app_state): State<Arc<AppState>>,
+    Json(schedule): Json<Schedule>,
+) -> Result<impl IntoResponse, CustomError>{
+    Category::read(&app_state.pool, schedule.get_category_id()).await?;
+    let schedule = Schedule::update(&app_state.pool, schedule).await?;
+    Ok((StatusCode::OK, Json(schedule)).into_response())
+}
+
+async fn delete(
+    State(app_state): State<Arc<AppState>>,
+    Path(schedule_id): Path<i64>,
+) -> Result<impl IntoResponse, CustomError>{
+    let schedule = Schedule::delete(&app_state.pool, schedule_id).await?;
+    Ok((StatusCode::OK, Json(schedule)).into_response())
+}
@@ -0,0 +1,32 @@
+use std::sync::Arc;
+use axum::{
+    Router,
+    Json,
+    extract::{State, Query},
+    routing,
+    response::IntoResponse,
+    http::StatusCode,
+};
+
+use crate::{
+    http::AppState,
+    models::{
+        search::{Hit, SearchParams},
+        error::CustomError
+    }
+};
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/api/v1/search",
+            routing::get(search)
+        )
+}
+
+async fn search(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<SearchParams>,
+) -> Result<impl IntoResponse, CustomError>{
+    let hits = Hit::search(&app_state.pool, &params).await?;
+    Ok((StatusCode::OK, Json(hits)).into_response())
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+use axum::{
+    Router,
+    Json,
+    extract::{State, Path},
+    routing,
+    response::IntoResponse,
+    http::StatusCode,
+};
+
+use crate::{
+    http::AppState,
+    models::{
+        category::Category,
+        template::{
+            Template,
+            NewTemplate,
+        },
+        error::CustomError
+    }
+};
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/api/v1/templates",
+            routing::get(read_all)
+        )
+        .route("/api/v1/templates",
+            routing::post(create)
+        )
+        .route("/api/v1/templates",
+            routing::put(update)
+        )
+        .route("/api/v1/templates/:id",
+            routing::get(read)
+        )
+        .route("/api/v1/templates/:id",
+            routing::delete(delete)
+        )
+}
+
+async fn create(
+    State(app_state): State<Arc<AppState>>,
+    Json(new_template): Json<NewTemplate>,
+) -> Result<impl IntoResponse, CustomError>{
+    Category::read(&app_state.pool, new_template.get_category_id()).await?;
+    let template = Template::create(&app_state.pool, new_template).await?;
+    Ok((StatusCode::OK, Json(template)).into_response())
+}
+
+async fn read(
+    State(app_state): State<Arc<AppState>>,
+    Path(template_id): Path<i64>,
+) -> Result<impl IntoResponse, CustomError>{
+    let template = Template::read(&app_state.pool, template_id).await?;
+    Ok((StatusCode::OK, Json(template)).into_response())
+}
+
+async fn read_all(
+    State(app_state): State<Arc<AppState>>
+) -> Result<impl IntoResponse, CustomError>{
+    let templates = Template::read_all(&app_state.pool).await?;
+    Ok((StatusCode::OK, Json(templates)).into_response())
+}
+
+async fn update(
+    State(app_state): State<Arc<AppState>>,
+    Json(template): Json<Template>,
+) -> Result<impl IntoResponse, CustomError>{
+    Category::read(&app_state.pool, template.get_category_id()).await?;
+    let template = Template::update(&app_state.pool, template).await?;
+    Ok((StatusCode::OK, Json(template)).into_response())
+}
+
+async fn delete(
+    State(app_state): State<Arc<AppState>>,
+    Path(template_id): Path<i64>,
+) -> Result<impl IntoResponse, CustomError>{
+    let template = Template::delete(&app_state.pool, template_id).await?;
+    Ok((StatusCode::OK, Json(template)).into_response())
+}
@@ -2,7 +2,7 @@ use std::sync::Arc;
 use axum::{
     Router,
     Json,
-    extract::{State, Path},
+    extract::{State, Path, Query},
     routing,
     response::IntoResponse,
     http::StatusCode,
@@ -15,6 +15,8 @@ use crate::{
             Tip,
             NewTip,
         },
+        listing::{ListParams, Page},
+        validation::Validate,
         error::CustomError
     }
 };
@@ -45,6 +47,7 @@ async fn create(
     State(app_state): State<Arc<AppState>>,
     Json(new_tip): Json<NewTip>,
 ) -> Result<impl IntoResponse, CustomError>{
+    new_tip.validate()?;
     let tip = Tip::create(&app_state.pool, new_tip).await?;
     Ok((StatusCode::OK, Json(serde_json::to_value(tip).unwrap())).into_response())
 }
@@ -57,17 +60,20 @@ async fn read(
     Ok((StatusCode::OK, Json(serde_json::to_value(tip).unwrap())).into_response())
 }
 
+/// A page of the tips, filtered and sorted as asked in the query
 async fn read_all(
-    State(app_state): State<Arc<AppState>>
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<ListParams>,
 ) -> Result<impl IntoResponse, CustomError>{
-    let tips = Tip::read_all(&app_state.pool).await?;
-    Ok((StatusCode::OK, Json(serde_json::to_value(tips).unwrap())).into_response())
+    let (tips, total) = Tip::read_page(&app_state.pool, &params).await?;
+    Ok((StatusCode::OK, Json(Page::new(tips, total, &params, "/api/v1/tips"))).into_response())
 }
 
 async fn update(
     State(app_state): State<Arc<AppState>>,
     Json(tip): Json<Tip>,
 ) -> Result<impl IntoResponse, CustomError>{
+    tip.validate()?;
     let tip = Tip::update(&app_state.pool, tip).await?;
     Ok((StatusCode::OK, Json(serde_json::to_value(tip).unwrap())).into_response())
 }
@@ -1,75 +1,34 @@
-use tokio;
-use std::env;
-use std::{path::Path, str::FromStr};
+use std::str::FromStr;
+use clap::Parser;
 use tracing_subscriber::{
     EnvFilter,
     layer::SubscriberExt,
     util::SubscriberInitExt
 };
-use tracing::{debug, info};
-use sqlx::{
-    Sqlite,
-    sqlite::SqlitePoolOptions,
-    migrate::{Migrator, MigrateDatabase}
-};
+use tracing::info;
 use dotenv::dotenv;
 
+use cli::Cli;
+
+mod cli;
+mod database;
 mod http;
 mod models;
+mod scheduler;
+#[cfg(test)]
+mod stub;
+mod worker;
 
 
 #[tokio::main]
-async fn main(){
+async fn main() -> anyhow::Result<()>{
     dotenv().ok();
-    let log_level = env::var("LOG_LEVEL").unwrap_or_else(|_|"DEBUG".to_string());
+    let cli = Cli::parse();
+    // the standard output is left for the commands
     tracing_subscriber::registry()
-        .with(EnvFilter::from_str(&log_level).unwrap())
-        .with(tracing_subscriber::fmt::layer())
+        .with(EnvFilter::from_str(&cli.log_level).unwrap())
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .init();
-    info!("Log level: {}", &log_level);
-    let db_url = env::var("DB_URL").unwrap_or_else(|_|"publirs.db".to_string());
-    info!("Database URL: {}", &db_url);
-    let port = env::var("PORT").unwrap_or_else(|_|"8080".to_string()).parse::<u16>().unwrap();
-    info!("Port: {}", &port);
-    let enviroment = env::var("ENVIRONMENT").unwrap_or_else(|_|"DEVELOPMENT".to_string());
-    info!("Environment: {}", &port);
-    let token = env::var("TOKEN").expect("TOKEN is mandatory");
-    debug!("Environment: {}", &token);
-
-    if !Sqlite::database_exists(&db_url).await.unwrap(){
-        Sqlite::create_database(&db_url).await.unwrap();
-    }
-
-    let migrations = if enviroment == "PRODUCTION"{
-        tracing::info!("PRODUCTION");
-        std::env::current_exe().unwrap().parent().unwrap().join("migrations")
-    }else{
-        tracing::info!("DEVELOPMENT");
-        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
-        Path::new(&crate_dir).join("./migrations")
-    };
-    debug!("Migrations: {:?}", migrations);
-
-    let pool = match SqlitePoolOptions::new()
-        .max_connections(1)
-        .connect(&db_url)
-        .await{
-            Ok(pool) => {
-                tracing::info!("✅Connection to the database is successful!");
-                pool
-            },
-            Err(err) => {
-                tracing::info!("🔥 Failed to connect to the database: {:?}", err);
-                std::process::exit(1);
-            }
-        };
-    Migrator::new(migrations)
-        .await
-        .unwrap()
-        .run(&pool)
-        .await
-        .unwrap();
-
-    tracing::info!("🚀 Server started successfully");
-    http::serve(&pool, &token, port).await.unwrap();
+    info!("Log level: {}", &cli.log_level);
+    cli.run().await
 }
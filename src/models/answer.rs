@@ -1,14 +1,25 @@
+use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
-use sqlx::{sqlite::{SqlitePool, SqliteRow}, query, Row};
-use super::error::CustomError;
+use sqlx::{sqlite::{Sqlite, SqlitePool, SqliteRow}, query, Executor, Row};
+use super::{
+    validation::{Errors, Validate, MAX_OPTION_LENGTH},
+    error::CustomError,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Answer{
+    /// The id and the poll are not needed when the answers of a poll are
+    /// replaced
+    #[serde(default)]
     id: i64,
+    #[serde(default)]
     poll_id: i64,
     text: String,
     #[serde(default = "get_default_isok")]
-    isok: bool
+    isok: bool,
+    /// Place of the answer in the poll, starting at 0
+    #[serde(default)]
+    position: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -25,10 +36,41 @@ pub struct NewBasicAnswer{
     pub isok: bool
 }
 
+/// Answer added to an existing poll, at the end if no position is given
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewPollAnswer{
+    pub text: String,
+    #[serde(default = "get_default_isok")]
+    pub isok: bool,
+    pub position: Option<i64>,
+}
+
+/// Changes to an answer, the missing fields are left as they are
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AnswerChanges{
+    pub text: Option<String>,
+    pub isok: Option<bool>,
+    pub position: Option<i64>,
+}
+
 fn get_default_isok() -> bool{
     false
 }
 
+impl Validate for NewPollAnswer{
+    fn check(&self, errors: &mut Errors){
+        errors.text("text", &self.text, MAX_OPTION_LENGTH);
+    }
+}
+
+impl Validate for AnswerChanges{
+    fn check(&self, errors: &mut Errors){
+        if let Some(text) = &self.text{
+            errors.text("text", text, MAX_OPTION_LENGTH);
+        }
+    }
+}
+
 impl NewAnswer{
     pub fn new(poll_id: i64, text: String, isok: bool) -> Self{
         Self{
@@ -39,16 +81,30 @@ impl NewAnswer{
     }
 }
 
+impl From<Answer> for NewBasicAnswer{
+    fn from(answer: Answer) -> Self{
+        Self{
+            text: answer.text,
+            isok: answer.isok,
+        }
+    }
+}
+
 impl Answer{
     fn from_row(row: SqliteRow) -> Self{
         Self{
             id: row.get("id"),
             poll_id: row.get("poll_id"),
             text: row.get("text"),
-            isok: row.get("isok")
+            isok: row.get("isok"),
+            position: row.get("position"),
         }
     }
 
+    pub fn get_poll_id(&self) -> i64{
+        self.poll_id
+    }
+
     pub fn get_text(&self) -> &str{
         &self.text
     }
@@ -57,22 +113,71 @@ impl Answer{
         self.isok
     }
 
-    pub async fn create(pool: &SqlitePool, new_poll: NewAnswer)
+    pub fn set_text(&mut self, text: String){
+        self.text = text;
+    }
+
+    pub fn set_isok(&mut self, isok: bool){
+        self.isok = isok;
+    }
+
+    /// Creates the answer after the last one of its poll
+    pub async fn create<'e, E: Executor<'e, Database = Sqlite>>(executor: E, new_poll: NewAnswer)
             -> Result<Answer,  CustomError>{
         tracing::info!("Data: {:?}", new_poll);
-        let sql = "INSERT INTO answers (poll_id, text, isok
-                   VALUES ($1, $2, $3) RETURNING *;";
+        let sql = "INSERT INTO answers (poll_id, text, isok, position)
+                   VALUES ($1, $2, $3, (SELECT COUNT(*) FROM answers WHERE poll_id = $1))
+                   RETURNING *;";
         query(sql)
             .bind(new_poll.poll_id)
             .bind(new_poll.text)
             .bind(new_poll.isok)
             .map(Self::from_row)
-            .fetch_one(pool)
+            .fetch_one(executor)
             .await
             .map_err(|e| {
                 CustomError::ServerError(e.to_string())
             })
     }
+
+    /// Creates the answer at the position, the answers from that position
+    /// on are moved one place down
+    pub async fn create_at(pool: &SqlitePool, new_answer: NewAnswer, position: i64)
+            -> Result<Answer, CustomError>{
+        tracing::info!("Data: {:?}", new_answer);
+        let mut tx = pool.begin()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        let count: i64 = query("SELECT COUNT(*) FROM answers WHERE poll_id = $1")
+            .bind(new_answer.poll_id)
+            .map(|row: SqliteRow| row.get(0))
+            .fetch_one(&mut tx)
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        let position = position.clamp(0, count);
+        query("UPDATE answers SET position = position + 1 WHERE poll_id = $1 AND position >= $2")
+            .bind(new_answer.poll_id)
+            .bind(position)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        let sql = "INSERT INTO answers (poll_id, text, isok, position)
+                   VALUES ($1, $2, $3, $4) RETURNING *;";
+        let answer = query(sql)
+            .bind(new_answer.poll_id)
+            .bind(new_answer.text)
+            .bind(new_answer.isok)
+            .bind(position)
+            .map(Self::from_row)
+            .fetch_one(&mut tx)
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        tx.commit()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        Ok(answer)
+    }
+
     pub async fn read(pool: &SqlitePool, id: i64) -> Result<Option<Answer>, CustomError>{
         let sql = "SELECT * FROM answers WHERE id = $1";
         query(sql)
@@ -86,7 +191,7 @@ impl Answer{
     }
 
     pub async fn read_all(pool: &SqlitePool) -> Result<Vec<Answer>, CustomError>{
-        let sql = "SELECT * FROM answers";
+        let sql = "SELECT * FROM answers ORDER BY poll_id, position, id";
         query(sql)
             .map(Self::from_row)
             .fetch_all(pool)
@@ -97,7 +202,7 @@ impl Answer{
     }
 
     pub async fn read_for_poll(pool: &SqlitePool, poll_id: i64) -> Result<Vec<Answer>, CustomError>{
-        let sql = "SELECT * FROM answers WHERE poll_id = $1 ORDER BY id";
+        let sql = "SELECT * FROM answers WHERE poll_id = $1 ORDER BY position, id";
         query(sql)
             .bind(poll_id)
             .map(Self::from_row)
@@ -108,24 +213,39 @@ impl Answer{
             })
     }
 
-    pub async fn get_correct_answer_for_poll(pool: &SqlitePool, poll_id: i64) -> Result<Option<i64>, CustomError>{
-        let sql = "SELECT * FROM answers WHERE poll_id = $1 ORDER BY id";
+    /// How many answers each poll has, the polls without answers are left
+    /// out
+    pub async fn count_by_poll(pool: &SqlitePool) -> Result<HashMap<i64, i64>, CustomError>{
+        let sql = "SELECT poll_id, COUNT(*) AS answers FROM answers GROUP BY poll_id";
+        query(sql)
+            .map(|row: SqliteRow| (row.get("poll_id"), row.get("answers")))
+            .fetch_all(pool)
+            .await
+            .map(|counts| counts.into_iter().collect())
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    pub async fn delete_for_poll<'e, E: Executor<'e, Database = Sqlite>>(executor: E, poll_id: i64) -> Result<Vec<Answer>, CustomError>{
+        let sql = "DELETE from answers WHERE poll_id = $1 RETURNING * ;";
         query(sql)
             .bind(poll_id)
-            .map(|result: SqliteRow| -> i64 {result.get(0)})
-            .fetch_optional(pool)
+            .map(Self::from_row)
+            .fetch_all(executor)
             .await
             .map_err(|e| {
                 CustomError::ServerError(e.to_string())
             })
     }
 
+    /// Updates the text and whether the answer is the right one, the
+    /// position is changed with `move_to`
     pub async fn update(pool: &SqlitePool, answer: Answer) -> Result<Answer, CustomError>{
-        let sql = "UPDATE answers SET poll_id = $2, text = $3, isok = $4
-                    FROM answers WHERE id = $1 RETURNING * ;";
+        let sql = "UPDATE answers SET text = $2, isok = $3
+                   WHERE id = $1 RETURNING * ;";
         query(sql)
             .bind(answer.id)
-            .bind(answer.poll_id)
             .bind(answer.text)
             .bind(answer.isok)
             .map(Self::from_row)
@@ -136,15 +256,69 @@ impl Answer{
             })
     }
 
+    /// Moves the answer to the position, the answers between the old and
+    /// the new position are moved one place to fill the gap
+    pub async fn move_to(pool: &SqlitePool, answer: Answer, position: i64) -> Result<Answer, CustomError>{
+        let mut tx = pool.begin()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        let last: i64 = query("SELECT COUNT(*) - 1 FROM answers WHERE poll_id = $1")
+            .bind(answer.poll_id)
+            .map(|row: SqliteRow| row.get(0))
+            .fetch_one(&mut tx)
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        let position = position.clamp(0, last.max(0));
+        let sql = if position > answer.position {
+            "UPDATE answers SET position = position - 1
+             WHERE poll_id = $1 AND position > $2 AND position <= $3"
+        }else{
+            "UPDATE answers SET position = position + 1
+             WHERE poll_id = $1 AND position >= $3 AND position < $2"
+        };
+        query(sql)
+            .bind(answer.poll_id)
+            .bind(answer.position)
+            .bind(position)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        let answer = query("UPDATE answers SET position = $2 WHERE id = $1 RETURNING *;")
+            .bind(answer.id)
+            .bind(position)
+            .map(Self::from_row)
+            .fetch_one(&mut tx)
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        tx.commit()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        Ok(answer)
+    }
+
+    /// Deletes the answer and moves the ones after it one place up
     pub async fn delete(pool: &SqlitePool, id: i64) -> Result<Answer, CustomError>{
+        let mut tx = pool.begin()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
         let sql = "DELETE from answers WHERE id = $1 RETURNING * ;";
-        query(sql)
+        let answer = query(sql)
             .bind(id)
             .map(Self::from_row)
-            .fetch_one(pool)
+            .fetch_one(&mut tx)
             .await
             .map_err(|e| {
                 CustomError::ServerError(e.to_string())
-            })
+            })?;
+        query("UPDATE answers SET position = position - 1 WHERE poll_id = $1 AND position > $2")
+            .bind(answer.poll_id)
+            .bind(answer.position)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        tx.commit()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        Ok(answer)
     }
 }
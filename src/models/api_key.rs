@@ -0,0 +1,119 @@
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use sqlx::{sqlite::{SqlitePool, SqliteRow}, query, Row};
+use super::error::CustomError;
+
+/// Length of the visible part of the key, used to identify it
+const PREFIX_LENGTH: usize = 8;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiKey{
+    id: i64,
+    name: String,
+    prefix: String,
+    created_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewApiKey{
+    name: String,
+}
+
+/// A just created key. This is the only time the secret is available,
+/// only its hash is stored.
+#[derive(Debug, Serialize, Clone)]
+pub struct CreatedApiKey{
+    #[serde(flatten)]
+    api_key: ApiKey,
+    key: String,
+}
+
+/// Hashes a secret to store it or to look for it
+pub fn hash(secret: &str) -> String{
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+impl ApiKey{
+    fn from_row(row: SqliteRow) -> Self{
+        Self{
+            id: row.get("id"),
+            name: row.get("name"),
+            prefix: row.get("prefix"),
+            created_at: row.get("created_at"),
+            last_used_at: row.get("last_used_at"),
+            revoked_at: row.get("revoked_at"),
+        }
+    }
+
+    pub fn get_name(&self) -> &str{
+        &self.name
+    }
+
+    pub async fn create(pool: &SqlitePool, new_api_key: NewApiKey)
+            -> Result<CreatedApiKey, CustomError>{
+        tracing::info!("Data: {:?}", new_api_key);
+        let secret = hex::encode(rand::thread_rng().gen::<[u8; 32]>());
+        let sql = "INSERT INTO api_keys (name, prefix, hash, created_at)
+                   VALUES ($1, $2, $3, $4) RETURNING *;";
+        query(sql)
+            .bind(new_api_key.name)
+            .bind(&secret[..PREFIX_LENGTH])
+            .bind(hash(&secret))
+            .bind(Utc::now())
+            .map(Self::from_row)
+            .fetch_one(pool)
+            .await
+            .map(|api_key| CreatedApiKey{
+                api_key,
+                key: secret,
+            })
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    pub async fn read_all(pool: &SqlitePool) -> Result<Vec<ApiKey>, CustomError>{
+        let sql = "SELECT * FROM api_keys ORDER BY id";
+        query(sql)
+            .map(Self::from_row)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// Looks for a not revoked key with this secret and, if found,
+    /// records that it has been used.
+    pub async fn authenticate(pool: &SqlitePool, secret: &str) -> Result<Option<ApiKey>, CustomError>{
+        let sql = "UPDATE api_keys SET last_used_at = $2
+                   WHERE hash = $1 AND revoked_at IS NULL RETURNING *;";
+        query(sql)
+            .bind(hash(secret))
+            .bind(Utc::now())
+            .map(Self::from_row)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    pub async fn revoke(pool: &SqlitePool, id: i64) -> Result<ApiKey, CustomError>{
+        let sql = "UPDATE api_keys SET revoked_at = $2
+                   WHERE id = $1 AND revoked_at IS NULL RETURNING *;";
+        query(sql)
+            .bind(id)
+            .bind(Utc::now())
+            .map(Self::from_row)
+            .fetch_one(pool)
+            .await
+            .map_err(|_| {
+                CustomError::NotFound
+            })
+    }
+}
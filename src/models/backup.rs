@@ -0,0 +1,382 @@
+use std::{collections::HashMap, str::FromStr};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use sqlx::sqlite::SqlitePool;
+use super::{
+    answer::{Answer, NewAnswer},
+    category::{self, Category, NewCategory},
+    import::{Entity, Report},
+    poll::Poll,
+    tip::Tip,
+    error::CustomError,
+};
+
+/// Every category, tip, poll and answer, with their ids and whether they
+/// have been published
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Backup{
+    pub exported_at: Option<DateTime<Utc>>,
+    #[serde(default, serialize_with = "category::serialize_with_secrets")]
+    pub categories: Vec<Category>,
+    #[serde(default)]
+    pub tips: Vec<Tip>,
+    #[serde(default)]
+    pub polls: Vec<Poll>,
+    #[serde(default)]
+    pub answers: Vec<Answer>,
+}
+
+/// What to do with an item that is already stored, a category with the
+/// same name or a tip or a poll with the same title or question in the same
+/// category
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict{
+    /// Keep the stored item, its tips and polls are restored in it
+    #[default]
+    Skip,
+    /// Overwrite the stored item with the one in the backup
+    Update,
+    /// Restore nothing
+    Fail,
+}
+
+/// Formats a backup can be written in
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format{
+    #[default]
+    Json,
+    Yaml,
+}
+
+impl FromStr for Format{
+    type Err = CustomError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s{
+            "json" => Ok(Self::Json),
+            "yaml" => Ok(Self::Yaml),
+            _ => Err(CustomError::BadRequest),
+        }
+    }
+}
+
+impl Backup{
+    pub fn from_yaml(content: &str) -> Result<Self, CustomError>{
+        serde_yaml::from_str(content)
+            .map_err(|e| CustomError::Parse(e.to_string()))
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, CustomError>{
+        serde_json::from_str(content)
+            .map_err(|e| CustomError::Parse(e.to_string()))
+    }
+
+    pub fn write(&self, format: Format) -> Result<String, CustomError>{
+        match format{
+            Format::Json => serde_json::to_string_pretty(self)
+                .map_err(|e| CustomError::ServerError(e.to_string())),
+            Format::Yaml => serde_yaml::to_string(self)
+                .map_err(|e| CustomError::ServerError(e.to_string())),
+        }
+    }
+
+    pub async fn export(pool: &SqlitePool) -> Result<Backup, CustomError>{
+        Ok(Self{
+            exported_at: Some(Utc::now()),
+            categories: Category::read_all(pool).await?,
+            tips: Tip::read_all(pool).await?,
+            polls: Poll::read_all(pool).await?,
+            answers: Answer::read_all(pool).await?,
+        })
+    }
+
+    /// Loads the backup. The items get new ids and the references between
+    /// them are updated, so it can be loaded in a database with content.
+    /// Everything is restored in a single transaction.
+    pub async fn restore(self, pool: &SqlitePool, on_conflict: OnConflict) -> Result<Report, CustomError>{
+        // The pool has a single connection, so everything has to be read
+        // before the transaction starts
+        let mut stored_categories: HashMap<String, i64> = Category::read_all(pool).await?
+            .into_iter()
+            .map(|category| (category.get_name().to_string(), category.get_id()))
+            .collect();
+        let mut stored_tips: HashMap<(i64, String), i64> = Tip::read_all(pool).await?
+            .into_iter()
+            .map(|tip| ((tip.get_category_id(), tip.get_title().to_string()), tip.get_id()))
+            .collect();
+        let mut stored_polls: HashMap<(i64, String), i64> = Poll::read_all(pool).await?
+            .into_iter()
+            .map(|poll| ((poll.get_category_id(), poll.get_question().to_string()), poll.get_id()))
+            .collect();
+        let mut answers: HashMap<i64, Vec<(usize, Answer)>> = HashMap::new();
+        for (index, answer) in self.answers.into_iter().enumerate(){
+            answers.entry(answer.get_poll_id()).or_default().push((index, answer));
+        }
+
+        let mut report = Report::default();
+        // ids in the backup to ids in the database
+        let mut categories: HashMap<i64, i64> = HashMap::new();
+        let mut tx = pool.begin()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+
+        for (index, mut category) in self.categories.into_iter().enumerate(){
+            let id = category.get_id();
+            let name = category.get_name().to_string();
+            match stored_categories.get(&name).copied(){
+                Some(stored_id) => match on_conflict{
+                    OnConflict::Skip => {
+                        report.skipped(Entity::Category, index, &name, "The category already exists");
+                    },
+                    OnConflict::Update => {
+                        category.set_id(stored_id);
+                        Category::update(&mut tx, category).await?;
+                        report.updated(Entity::Category, index, &name, stored_id);
+                    },
+                    OnConflict::Fail => return Err(conflict("Category", &name)),
+                },
+                None => {
+                    let category = Category::create(&mut tx, NewCategory::from(category)).await?;
+                    stored_categories.insert(name.clone(), category.get_id());
+                    report.created(Entity::Category, index, &name, category.get_id());
+                },
+            }
+            categories.insert(id, stored_categories[&name]);
+        }
+
+        for (index, mut tip) in self.tips.into_iter().enumerate(){
+            let title = tip.get_title().to_string();
+            let category_id = match categories.get(&tip.get_category_id()){
+                Some(category_id) => *category_id,
+                None => {
+                    report.failed(Entity::Tip, index, &title, missing("category", tip.get_category_id()));
+                    continue;
+                },
+            };
+            tip.set_category_id(category_id);
+            let key = (category_id, title.clone());
+            match stored_tips.get(&key).copied(){
+                Some(stored_id) => match on_conflict{
+                    OnConflict::Skip => {
+                        report.skipped(Entity::Tip, index, &title, "The tip already exists");
+                    },
+                    OnConflict::Update => {
+                        tip.set_id(stored_id);
+                        Tip::replace(&mut tx, tip).await?;
+                        report.updated(Entity::Tip, index, &title, stored_id);
+                    },
+                    OnConflict::Fail => return Err(conflict("Tip", &title)),
+                },
+                None => {
+                    let created = Tip::restore(&mut tx, tip).await?;
+                    stored_tips.insert(key, created.get_id());
+                    report.created(Entity::Tip, index, &title, created.get_id());
+                },
+            }
+        }
+
+        for (index, mut poll) in self.polls.into_iter().enumerate(){
+            let question = poll.get_question().to_string();
+            let poll_answers = answers.remove(&poll.get_id()).unwrap_or_default();
+            let category_id = match categories.get(&poll.get_category_id()){
+                Some(category_id) => *category_id,
+                None => {
+                    report.failed(Entity::Poll, index, &question, missing("category", poll.get_category_id()));
+                    continue;
+                },
+            };
+            poll.set_category_id(category_id);
+            let key = (category_id, question.clone());
+            let poll_id = match stored_polls.get(&key).copied(){
+                Some(stored_id) => match on_conflict{
+                    OnConflict::Skip => {
+                        report.skipped(Entity::Poll, index, &question, "The poll already exists");
+                        continue;
+                    },
+                    OnConflict::Update => {
+                        poll.set_id(stored_id);
+                        Poll::replace(&mut tx, poll).await?;
+                        Answer::delete_for_poll(&mut tx, stored_id).await?;
+                        report.updated(Entity::Poll, index, &question, stored_id);
+                        stored_id
+                    },
+                    OnConflict::Fail => return Err(conflict("Poll", &question)),
+                },
+                None => {
+                    let created = Poll::restore(&mut tx, poll).await?;
+                    stored_polls.insert(key, created.get_id());
+                    report.created(Entity::Poll, index, &question, created.get_id());
+                    created.get_id()
+                },
+            };
+            for (_, answer) in poll_answers{
+                let new_answer = NewAnswer::new(poll_id, answer.get_text().to_string(), answer.get_isok());
+                Answer::create(&mut tx, new_answer).await?;
+            }
+        }
+
+        // Answers of polls that are not in the backup
+        for (poll_id, orphans) in answers{
+            for (index, answer) in orphans{
+                report.failed(Entity::Answer, index, answer.get_text(), missing("poll", poll_id));
+            }
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        report.sort();
+        Ok(report)
+    }
+}
+
+fn conflict(entity: &str, name: &str) -> CustomError{
+    CustomError::Conflict(format!("{} {} already exists", entity, name))
+}
+
+fn missing(entity: &str, id: i64) -> String{
+    format!("The {} {} is not in the backup", entity, id)
+}
+
+#[cfg(test)]
+mod tests{
+    use serde_json::{json, Value};
+    use crate::database;
+    use super::*;
+
+    /// A backup made in another database, so none of its ids are free
+    fn backup() -> Backup{
+        serde_json::from_value(json!({
+            "categories": [
+                {"id": 7, "name": "Rust", "chat_id": "@rust", "thread_id": 0},
+            ],
+            "tips": [
+                {"id": 20, "category_id": 7, "title": "Borrow", "text": "Only one &mut",
+                 "published": true, "published_count": 3,
+                 "last_published_at": "2023-07-01T10:00:00Z"},
+                {"id": 21, "category_id": 9, "title": "Lost", "text": "No category"},
+            ],
+            "polls": [
+                {"id": 30, "category_id": 7, "question": "Which one moves?",
+                 "published": true, "published_count": 2,
+                 "last_published_at": "2023-07-02T10:00:00Z"},
+            ],
+            "answers": [
+                {"id": 40, "poll_id": 30, "text": "String", "isok": true},
+                {"id": 41, "poll_id": 30, "text": "i32", "isok": false},
+                {"id": 42, "poll_id": 31, "text": "Orphan", "isok": false},
+            ],
+        })).unwrap()
+    }
+
+    fn summary(report: &Report) -> (Value, Value, Value, Value){
+        let report = serde_json::to_value(report).unwrap();
+        (report["created"].clone(), report["updated"].clone(),
+            report["skipped"].clone(), report["failed"].clone())
+    }
+
+    async fn stored_tip(pool: &SqlitePool) -> Value{
+        serde_json::to_value(&Tip::read_all(pool).await.unwrap()[0]).unwrap()
+    }
+
+    async fn stored_poll(pool: &SqlitePool) -> Value{
+        serde_json::to_value(&Poll::read_all(pool).await.unwrap()[0]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn restores_in_an_empty_database_with_new_ids(){
+        let pool = database::memory().await;
+
+        let report = backup().restore(&pool, OnConflict::Skip).await.unwrap();
+
+        assert_eq!(summary(&report), (json!(3), json!(0), json!(0), json!(2)));
+        let category = Category::search(&pool, "Rust").await.unwrap();
+        assert_ne!(category.get_id(), 7);
+        let tip = stored_tip(&pool).await;
+        assert_eq!(tip["category_id"], json!(category.get_id()));
+        assert_eq!(tip["published"], json!(true));
+        assert_eq!(tip["published_count"], json!(3));
+        assert_eq!(tip["last_published_at"], json!("2023-07-01T10:00:00Z"));
+        let poll = stored_poll(&pool).await;
+        assert_eq!(poll["category_id"], json!(category.get_id()));
+        assert_eq!(poll["published"], json!(true));
+        assert_eq!(poll["published_count"], json!(2));
+        let answers = Answer::read_for_poll(&pool, poll["id"].as_i64().unwrap()).await.unwrap();
+        let texts: Vec<&str> = answers.iter().map(|answer| answer.get_text()).collect();
+        assert_eq!(texts, vec!["String", "i32"]);
+    }
+
+    /// Stores the same category, tip and poll with nothing published
+    async fn with_content() -> SqlitePool{
+        let pool = database::memory().await;
+        let mut content = backup();
+        content.tips.truncate(1);
+        content.answers.truncate(2);
+        let mut content: Value = serde_json::to_value(content).unwrap();
+        content["categories"][0]["chat_id"] = json!("@rust_old");
+        for item in ["tips", "polls"]{
+            content[item][0]["published"] = json!(false);
+            content[item][0]["published_count"] = json!(0);
+            content[item][0]["last_published_at"] = Value::Null;
+        }
+        let content: Backup = serde_json::from_value(content).unwrap();
+        content.restore(&pool, OnConflict::Fail).await.unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn skips_what_is_already_stored(){
+        let pool = with_content().await;
+
+        let report = backup().restore(&pool, OnConflict::Skip).await.unwrap();
+
+        assert_eq!(summary(&report), (json!(0), json!(0), json!(3), json!(2)));
+        assert_eq!(Category::search(&pool, "Rust").await.unwrap().get_chat_id(), "@rust_old");
+        assert_eq!(stored_tip(&pool).await["published_count"], json!(0));
+        assert_eq!(stored_poll(&pool).await["published_count"], json!(0));
+        assert_eq!(Tip::read_all(&pool).await.unwrap().len(), 1);
+        assert_eq!(Answer::read_all(&pool).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn updates_what_is_already_stored(){
+        let pool = with_content().await;
+
+        let report = backup().restore(&pool, OnConflict::Update).await.unwrap();
+
+        assert_eq!(summary(&report), (json!(0), json!(3), json!(0), json!(2)));
+        assert_eq!(Category::search(&pool, "Rust").await.unwrap().get_chat_id(), "@rust");
+        let tip = stored_tip(&pool).await;
+        assert_eq!(tip["published"], json!(true));
+        assert_eq!(tip["published_count"], json!(3));
+        assert_eq!(tip["last_published_at"], json!("2023-07-01T10:00:00Z"));
+        let poll = stored_poll(&pool).await;
+        assert_eq!(poll["published_count"], json!(2));
+        assert_eq!(Answer::read_all(&pool).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn restores_nothing_on_a_conflict_when_asked_to_fail(){
+        let pool = database::memory().await;
+        let category: Category = serde_json::from_value(
+            json!({"id": 1, "name": "Go", "chat_id": "@go", "thread_id": 0})).unwrap();
+        let mut content = backup();
+        content.categories.insert(0, category);
+        content.categories[1].set_id(8);
+        content.tips[0].set_category_id(8);
+        let stored = Backup{
+            categories: vec![content.categories[1].clone()],
+            ..Backup::default()
+        };
+        stored.restore(&pool, OnConflict::Fail).await.unwrap();
+
+        match content.restore(&pool, OnConflict::Fail).await{
+            Err(CustomError::Conflict(e)) => assert!(e.contains("Rust"), "{}", e),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert!(matches!(Category::search(&pool, "Go").await, Err(CustomError::NotFound)));
+        assert!(Tip::read_all(&pool).await.unwrap().is_empty());
+    }
+}
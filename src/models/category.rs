@@ -1,6 +1,13 @@
-use serde::{Serialize, Deserialize};
-use sqlx::{sqlite::{SqlitePool, SqliteRow}, query, Row};
-use super::error::CustomError;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use sqlx::{sqlite::{Sqlite, SqlitePool, SqliteRow}, query, Executor, Row};
+use super::{
+    decode,
+    publisher::Backend,
+    strategy::Strategy,
+    listing::{Columns, ListParams},
+    validation::{Errors, Validate},
+    error::CustomError,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Category{
@@ -8,6 +15,33 @@ pub struct Category{
     name: String,
     chat_id: String,
     thread_id: i64,
+    #[serde(default = "get_default_backend")]
+    backend: Backend,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    mastodon_url: Option<String>,
+    /// Not sent to the clients, it is only written in the backups
+    #[serde(default, deserialize_with = "empty_as_none", skip_serializing)]
+    mastodon_token: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    matrix_homeserver: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    matrix_room_id: Option<String>,
+    /// Not sent to the clients, it is only written in the backups
+    #[serde(default, deserialize_with = "empty_as_none", skip_serializing)]
+    matrix_token: Option<String>,
+    /// Not sent to the clients, it is only written in the backups
+    #[serde(default, deserialize_with = "empty_as_none", skip_serializing)]
+    discord_webhook: Option<String>,
+    /// How the next tip or poll to publish is chosen
+    #[serde(default)]
+    strategy: Strategy,
+    /// Whether to publish again what was already published when there is
+    /// nothing new
+    #[serde(default)]
+    recycle: bool,
+    /// Days before something published can be published again
+    #[serde(default = "get_default_cooldown")]
+    cooldown: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -15,16 +49,177 @@ pub struct NewCategory{
     name: String,
     chat_id: String,
     thread_id: i64,
+    #[serde(default = "get_default_backend")]
+    backend: Backend,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    mastodon_url: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    mastodon_token: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    matrix_homeserver: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    matrix_room_id: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    matrix_token: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    discord_webhook: Option<String>,
+    #[serde(default)]
+    strategy: Strategy,
+    #[serde(default)]
+    recycle: bool,
+    #[serde(default = "get_default_cooldown")]
+    cooldown: i64,
 }
 
-impl Category{
-    fn from_row(row: SqliteRow) -> Self{
+/// How many tips and polls of a category are queued again
+#[derive(Debug, Serialize)]
+pub struct Reset{
+    pub tips: u64,
+    pub polls: u64,
+}
+
+const COLUMNS: Columns = Columns{
+    table: "categories",
+    search: &["name", "chat_id"],
+    sort: &["id", "name", "backend"],
+    categorized: false,
+};
+
+fn get_default_backend() -> Backend{
+    Backend::Telegram
+}
+
+fn get_default_cooldown() -> i64{
+    30
+}
+
+/// A category with the settings that are not sent to the clients, used to
+/// write the backups
+#[derive(Serialize)]
+struct WithSecrets<'a>{
+    #[serde(flatten)]
+    category: &'a Category,
+    mastodon_token: &'a Option<String>,
+    matrix_token: &'a Option<String>,
+    discord_webhook: &'a Option<String>,
+}
+
+/// Serializes the categories with their secrets
+pub fn serialize_with_secrets<S: Serializer>(categories: &[Category], serializer: S) -> Result<S::Ok, S::Error>{
+    serializer.collect_seq(categories.iter().map(|category| WithSecrets{
+        category,
+        mastodon_token: &category.mastodon_token,
+        matrix_token: &category.matrix_token,
+        discord_webhook: &category.discord_webhook,
+    }))
+}
+
+/// The fields left blank in a form are sent as empty strings
+fn empty_as_none<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error>{
+    let value = Option::<String>::deserialize(deserializer)?;
+    Ok(value.filter(|value| !value.trim().is_empty()))
+}
+
+impl From<Category> for NewCategory{
+    fn from(category: Category) -> Self{
+        Self{
+            name: category.name,
+            chat_id: category.chat_id,
+            thread_id: category.thread_id,
+            backend: category.backend,
+            mastodon_url: category.mastodon_url,
+            mastodon_token: category.mastodon_token,
+            matrix_homeserver: category.matrix_homeserver,
+            matrix_room_id: category.matrix_room_id,
+            matrix_token: category.matrix_token,
+            discord_webhook: category.discord_webhook,
+            strategy: category.strategy,
+            recycle: category.recycle,
+            cooldown: category.cooldown,
+        }
+    }
+}
+
+impl NewCategory{
+    /// A category without the settings of the other backends, these can be
+    /// set later
+    pub fn new(name: String, chat_id: String, thread_id: i64, backend: Backend) -> Self{
         Self{
+            name,
+            chat_id,
+            thread_id,
+            backend,
+            mastodon_url: None,
+            mastodon_token: None,
+            matrix_homeserver: None,
+            matrix_room_id: None,
+            matrix_token: None,
+            discord_webhook: None,
+            strategy: Strategy::default(),
+            recycle: false,
+            cooldown: get_default_cooldown(),
+        }
+    }
+
+    pub fn get_name(&self) -> &str{
+        &self.name
+    }
+}
+
+impl Validate for NewCategory{
+    /// The name and the settings needed by the backend of the category
+    fn check(&self, errors: &mut Errors){
+        errors.required("name", &self.name);
+        let backend = self.backend.to_string();
+        match self.backend{
+            Backend::Telegram => {
+                errors.required("chat_id", &self.chat_id);
+            },
+            Backend::Mastodon => {
+                errors.required_by("mastodon_url", self.mastodon_url.as_deref(), &backend);
+                errors.required_by("mastodon_token", self.mastodon_token.as_deref(), &backend);
+            },
+            Backend::Matrix => {
+                errors.required_by("matrix_homeserver", self.matrix_homeserver.as_deref(), &backend);
+                errors.required_by("matrix_room_id", self.matrix_room_id.as_deref(), &backend);
+                errors.required_by("matrix_token", self.matrix_token.as_deref(), &backend);
+            },
+            Backend::Discord => {
+                errors.required_by("discord_webhook", self.discord_webhook.as_deref(), &backend);
+            },
+        }
+        if self.cooldown < 0 {
+            errors.add("cooldown", "can't be negative".to_string());
+        }
+    }
+}
+
+impl Validate for Category{
+    fn check(&self, errors: &mut Errors){
+        NewCategory::from(self.clone()).check(errors);
+    }
+}
+
+impl Category{
+    /// Reads a category, a backend or a strategy that is not known is an
+    /// error, the content would be sent where it doesn't belong
+    fn from_row(row: SqliteRow) -> Result<Self, sqlx::Error>{
+        Ok(Self{
             id: row.get("id"),
             name: row.get("name"),
             chat_id: row.get("chat_id"),
-            thread_id: row.get("thread_id")
-        }
+            thread_id: row.get("thread_id"),
+            backend: decode(&row, "backend")?,
+            mastodon_url: row.get("mastodon_url"),
+            mastodon_token: row.get("mastodon_token"),
+            matrix_homeserver: row.get("matrix_homeserver"),
+            matrix_room_id: row.get("matrix_room_id"),
+            matrix_token: row.get("matrix_token"),
+            discord_webhook: row.get("discord_webhook"),
+            strategy: decode(&row, "strategy")?,
+            recycle: row.get("recycle"),
+            cooldown: row.get("cooldown"),
+        })
     }
 
     pub fn get_id(&self) -> i64{
@@ -39,21 +234,84 @@ impl Category{
         &self.chat_id
     }
 
+    pub fn set_id(&mut self, id: i64){
+        self.id = id;
+    }
+
     pub fn get_thread_id(&self) -> i64{
         self.thread_id
     }
 
-    pub async fn create(pool: &SqlitePool, new_category: NewCategory)
+    pub fn get_backend(&self) -> Backend{
+        self.backend
+    }
+
+    pub fn get_mastodon_url(&self) -> Option<&str>{
+        self.mastodon_url.as_deref()
+    }
+
+    /// Takes the secrets not given from the stored category. They are not
+    /// sent to the clients, so they come back empty when they are unchanged.
+    pub fn keep_secrets(&mut self, stored: Category){
+        if self.mastodon_token.is_none() {
+            self.mastodon_token = stored.mastodon_token;
+        }
+        if self.matrix_token.is_none() {
+            self.matrix_token = stored.matrix_token;
+        }
+        if self.discord_webhook.is_none() {
+            self.discord_webhook = stored.discord_webhook;
+        }
+    }
+
+    pub fn get_mastodon_token(&self) -> Option<&str>{
+        self.mastodon_token.as_deref()
+    }
+
+    pub fn get_matrix_homeserver(&self) -> Option<&str>{
+        self.matrix_homeserver.as_deref()
+    }
+
+    pub fn get_matrix_room_id(&self) -> Option<&str>{
+        self.matrix_room_id.as_deref()
+    }
+
+    pub fn get_matrix_token(&self) -> Option<&str>{
+        self.matrix_token.as_deref()
+    }
+
+    pub fn get_discord_webhook(&self) -> Option<&str>{
+        self.discord_webhook.as_deref()
+    }
+
+    pub fn get_strategy(&self) -> Strategy{
+        self.strategy
+    }
+
+    pub async fn create<'e, E: Executor<'e, Database = Sqlite>>(executor: E, new_category: NewCategory)
             -> Result<Category, CustomError>{
         tracing::info!("Data: {:?}", new_category);
-        let sql = "INSERT INTO categories (name, chat_id, thread_id) 
-                   VALUES ($1, $2, $3) RETURNING *;";
+        let sql = "INSERT INTO categories (name, chat_id, thread_id, backend,
+                   mastodon_url, mastodon_token, matrix_homeserver,
+                   matrix_room_id, matrix_token, discord_webhook, strategy, recycle,
+                   cooldown)
+                   VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13) RETURNING *;";
         query(sql)
             .bind(new_category.name)
             .bind(new_category.chat_id)
             .bind(new_category.thread_id)
-            .map(Self::from_row)
-            .fetch_one(pool)
+            .bind(new_category.backend.as_str())
+            .bind(new_category.mastodon_url)
+            .bind(new_category.mastodon_token)
+            .bind(new_category.matrix_homeserver)
+            .bind(new_category.matrix_room_id)
+            .bind(new_category.matrix_token)
+            .bind(new_category.discord_webhook)
+            .bind(new_category.strategy.as_str())
+            .bind(new_category.recycle)
+            .bind(new_category.cooldown)
+            .try_map(Self::from_row)
+            .fetch_one(executor)
             .await
             .map_err(|e| {
                 CustomError::ServerError(e.to_string())
@@ -63,11 +321,12 @@ impl Category{
         let sql = "SELECT * FROM categories WHERE id = $1";
         query(sql)
             .bind(id)
-            .map(Self::from_row)
+            .try_map(Self::from_row)
             .fetch_one(pool)
             .await
-            .map_err(|_| {
-                CustomError::NotFound
+            .map_err(|e| match e{
+                sqlx::Error::RowNotFound => CustomError::NotFound,
+                e => CustomError::ServerError(e.to_string()),
             })
     }
 
@@ -75,11 +334,12 @@ impl Category{
         let sql = "SELECT * FROM categories WHERE name = $1";
         query(sql)
             .bind(name)
-            .map(Self::from_row)
+            .try_map(Self::from_row)
             .fetch_one(pool)
             .await
-            .map_err(|_| {
-                CustomError::NotFound
+            .map_err(|e| match e{
+                sqlx::Error::RowNotFound => CustomError::NotFound,
+                e => CustomError::ServerError(e.to_string()),
             })
     }
 
@@ -87,35 +347,80 @@ impl Category{
     pub async fn read_all(pool: &SqlitePool) -> Result<Vec<Category>, CustomError>{
         let sql = "SELECT * FROM categories";
         query(sql)
-            .map(Self::from_row)
+            .try_map(Self::from_row)
             .fetch_all(pool)
             .await
-            .map_err(|_| {
-                CustomError::NotFound
-            })
+            .map_err(|e| CustomError::ServerError(e.to_string()))
+    }
+
+    /// Reads a page of the categories and how many there are with the filters
+    pub async fn read_page(pool: &SqlitePool, params: &ListParams) -> Result<(Vec<Category>, i64), CustomError>{
+        params.read(pool, &COLUMNS, Self::from_row).await
     }
 
-    pub async fn update(pool: &SqlitePool, category: Category) -> Result<Category, CustomError>{
-        let sql = "UPDATE categories SET name = $2, chat_id = $3, thread_id = $4
-                    FROM categories WHERE id = $1 RETURNING * ;";
+    pub async fn update<'e, E: Executor<'e, Database = Sqlite>>(executor: E, category: Category) -> Result<Category, CustomError>{
+        let sql = "UPDATE categories SET name = $2, chat_id = $3, thread_id = $4,
+                   backend = $5, mastodon_url = $6, mastodon_token = $7,
+                   matrix_homeserver = $8, matrix_room_id = $9, matrix_token = $10,
+                   discord_webhook = $11, strategy = $12, recycle = $13, cooldown = $14
+                   WHERE id = $1 RETURNING * ;";
         query(sql)
             .bind(category.id)
             .bind(category.name)
             .bind(category.chat_id)
             .bind(category.thread_id)
-            .map(Self::from_row)
-            .fetch_one(pool)
+            .bind(category.backend.as_str())
+            .bind(category.mastodon_url)
+            .bind(category.mastodon_token)
+            .bind(category.matrix_homeserver)
+            .bind(category.matrix_room_id)
+            .bind(category.matrix_token)
+            .bind(category.discord_webhook)
+            .bind(category.strategy.as_str())
+            .bind(category.recycle)
+            .bind(category.cooldown)
+            .try_map(Self::from_row)
+            .fetch_one(executor)
             .await
             .map_err(|e|{
                 CustomError::ServerError(e.to_string())
             })
     }
 
+    /// Starts the cycle of the category again, everything it has is queued
+    /// to be published. How many times each item was published is kept.
+    pub async fn reset(pool: &SqlitePool, id: i64) -> Result<Reset, CustomError>{
+        // The pool has a single connection, so it has to be read before the
+        // transaction starts
+        Self::read(pool, id).await?;
+        let mut tx = pool.begin()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        let mut reset = Reset{
+            tips: 0,
+            polls: 0,
+        };
+        for (table, count) in [("tips", &mut reset.tips), ("polls", &mut reset.polls)]{
+            let sql = format!("UPDATE {} SET published = FALSE
+                               WHERE category_id = $1 AND published = TRUE", table);
+            *count = query(&sql)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .map_err(|e| CustomError::ServerError(e.to_string()))?
+                .rows_affected();
+        }
+        tx.commit()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        Ok(reset)
+    }
+
     pub async fn delete(pool: &SqlitePool, id: i64) -> Result<Category, CustomError>{
         let sql = "DELETE from categories WHERE id = $1 RETURNING * ;";
         query(sql)
             .bind(id)
-            .map(Self::from_row)
+            .try_map(Self::from_row)
             .fetch_one(pool)
             .await
             .map_err(|e|{
@@ -123,3 +428,25 @@ impl Category{
             })
     }
 }
+
+#[cfg(test)]
+mod tests{
+    use crate::database;
+    use super::*;
+
+    #[tokio::test]
+    async fn an_unknown_backend_is_an_error(){
+        let pool = database::memory().await;
+        query("INSERT INTO categories (name, chat_id, thread_id, backend)
+               VALUES ('Rust', '', 0, 'irc')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        match Category::read(&pool, 1).await{
+            Err(CustomError::ServerError(e)) => assert!(e.contains("unknown backend irc"), "{}", e),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert!(matches!(Category::read(&pool, 2).await, Err(CustomError::NotFound)));
+    }
+}
@@ -0,0 +1,235 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use tracing::{info, warn, error};
+
+use super::{
+    category::Category,
+    markup,
+    publisher::{Publisher, Message, Quiz, Receipt},
+    error::CustomError,
+};
+
+/// Times a message is sent again when Discord asks to slow down
+const MAX_RETRIES: usize = 3;
+/// Maximum time to wait for the rate limit across all the retries
+const MAX_WAIT: Duration = Duration::from_secs(60);
+/// Maximum time to establish the connection with Discord
+const CONNECT_TIMEOUT: u64 = 10;
+/// Maximum time for each request
+const TIMEOUT: u64 = 30;
+/// Maximum length of the title of an embed
+const MAX_TITLE_LENGTH: usize = 256;
+/// Maximum length of the description of an embed
+const MAX_DESCRIPTION_LENGTH: usize = 4096;
+/// Maximum length of the content of a message
+const MAX_CONTENT_LENGTH: usize = 2000;
+/// Emojis used to number the options of a poll, so they can be used as
+/// reactions
+const NUMBERS: [&str; 10] = ["1️⃣", "2️⃣", "3️⃣", "4️⃣", "5️⃣", "6️⃣", "7️⃣", "8️⃣", "9️⃣", "🔟"];
+
+/// Client for the Discord incoming webhooks. The webhook is set in each
+/// category.
+#[derive(Debug)]
+pub struct Discord {
+    client: Client,
+}
+
+/// Cuts the text to the given length
+fn truncate(text: &str, length: usize) -> String{
+    if text.chars().count() <= length {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(length - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Time to wait before retrying, as told by Discord in a 429 response. A
+/// wait too long to be represented is taken as the longest one.
+fn retry_after(body: &Value) -> Duration{
+    let seconds = body.get("retry_after")
+        .and_then(|retry_after| retry_after.as_f64())
+        .filter(|seconds| *seconds >= 0.0)
+        .unwrap_or(1.0);
+    Duration::try_from_secs_f64(seconds).unwrap_or(Duration::MAX)
+}
+
+impl Discord {
+    pub fn new() -> Self{
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT))
+            .timeout(Duration::from_secs(TIMEOUT))
+            .build()
+            .expect("Can't build the HTTP client");
+        Self {
+            client,
+        }
+    }
+
+    async fn execute(&self, category: &Category, message: Value) -> Result<String, CustomError>{
+        let webhook = category.get_discord_webhook()
+            .ok_or_else(|| CustomError::OtherError(format!(
+                "Category {} has no Discord webhook configured",
+                category.get_name())))?;
+        tracing::debug!("Message: {}", &message);
+        let mut retries = 0;
+        let mut waited = Duration::ZERO;
+        loop {
+            let response = self.client
+                .post(webhook)
+                .query(&[("wait", "true")])
+                .json(&message)
+                .send()
+                .await
+                .map_err(|error| {
+                    // The URL of the webhook has its token
+                    let error = error.without_url();
+                    error!("Could not send the message to Discord: {}", error);
+                    CustomError::OtherError(error.to_string())
+                })?;
+            let status = response.status();
+            let body: Value = response.json()
+                .await
+                .unwrap_or(Value::Null);
+            // After the last retry, or when the wait would be too long, the
+            // rate limit is returned as an error
+            let wait = retry_after(&body);
+            if status == StatusCode::TOO_MANY_REQUESTS && retries < MAX_RETRIES
+                    && waited.saturating_add(wait) <= MAX_WAIT {
+                retries += 1;
+                waited += wait;
+                warn!("Discord rate limit reached, waiting {:?}", wait);
+                tokio::time::sleep(wait).await;
+                continue;
+            }
+            if status.is_success() {
+                if let Some(id) = body.get("id").and_then(|id| id.as_str()) {
+                    info!("Message sent to Discord: {}", id);
+                    return Ok(id.to_string());
+                }
+            }
+            let error_message = body.get("message")
+                .and_then(|message| message.as_str())
+                .unwrap_or("unexpected response");
+            error!("Could not send the message to Discord: {} {}", status, error_message);
+            return Err(CustomError::OtherError(format!("Discord: {} {}", status, error_message)));
+        }
+    }
+}
+
+#[async_trait]
+impl Publisher for Discord{
+    async fn send_text(&self, category: &Category, message: &Message) -> Result<Receipt, CustomError>{
+        let message = self.preview_text(category, message)?;
+        let id = self.execute(category, message).await?;
+        Ok(Receipt{
+            message_id: Some(id),
+        })
+    }
+
+    async fn send_quiz(&self, category: &Category, quiz: &Quiz) -> Result<Receipt, CustomError>{
+        let message = self.preview_quiz(category, quiz)?;
+        let id = self.execute(category, message).await?;
+        Ok(Receipt{
+            message_id: Some(id),
+        })
+    }
+
+    fn preview_text(&self, category: &Category, message: &Message) -> Result<Value, CustomError>{
+        Ok(json!({
+            "embeds": [{
+                "title": truncate(&markup::html_to_text(&message.title), MAX_TITLE_LENGTH),
+                "description": truncate(&markup::html_to_markdown(&message.text), MAX_DESCRIPTION_LENGTH),
+                "footer": {"text": markup::hashtag(category.get_name())},
+            }],
+        }))
+    }
+
+    fn preview_quiz(&self, _category: &Category, quiz: &Quiz) -> Result<Value, CustomError>{
+        let options: Vec<String> = quiz.options.iter()
+            .zip(NUMBERS)
+            .map(|(option, number)| format!("{} {}", number, markup::html_to_markdown(option)))
+            .collect();
+        let answer = options.get(quiz.correct_option_id)
+            .ok_or_else(|| CustomError::OtherError("The poll has no right answer".to_string()))?;
+        let content = format!(
+            "**{}**\n\n{}\n\nReact with the number of your answer.\nAnswer: ||{}||",
+            markup::html_to_markdown(&quiz.question),
+            options.join("\n"),
+            answer);
+        Ok(json!({
+            "content": truncate(&content, MAX_CONTENT_LENGTH),
+        }))
+    }
+
+    fn max_send_time(&self) -> Duration{
+        Duration::from_secs(TIMEOUT) * (MAX_RETRIES as u32 + 1) + MAX_WAIT
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use std::time::Instant;
+    use crate::stub::Stub;
+    use super::*;
+
+    fn category(webhook: &str) -> Category{
+        serde_json::from_value(json!({
+            "id": 1,
+            "name": "Rust",
+            "chat_id": "",
+            "thread_id": 0,
+            "backend": "discord",
+            "discord_webhook": webhook,
+        })).unwrap()
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_rate_limit_without_waiting(){
+        let stub = Stub::start(vec![(429, json!({"retry_after": 0.2, "message": "rate limited"}))]);
+        let message = Message{
+            title: "Tip".to_string(),
+            text: "Borrow".to_string(),
+            html: "<b>Tip</b>\n\nBorrow".to_string(),
+        };
+
+        let start = Instant::now();
+        let result = Discord::new().send_text(&category(&format!("{}/webhook", stub.url)), &message).await;
+
+        match result{
+            Err(CustomError::OtherError(message)) => assert!(message.contains("429")),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert_eq!(stub.requests().len(), MAX_RETRIES + 1);
+        let waited = start.elapsed();
+        assert!(waited >= Duration::from_millis(600) && waited < Duration::from_millis(800),
+            "waited {:?}", waited);
+    }
+
+    #[test]
+    fn reads_the_wait_of_the_rate_limit(){
+        assert_eq!(retry_after(&json!({"retry_after": 1e300})), Duration::MAX);
+        assert_eq!(retry_after(&json!({"retry_after": -5.0})), Duration::from_secs(1));
+        assert_eq!(retry_after(&json!({"retry_after": "soon"})), Duration::from_secs(1));
+        assert_eq!(retry_after(&json!({"retry_after": 0.5})), Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn does_not_wait_longer_than_the_maximum(){
+        let stub = Stub::start(vec![(429, json!({"retry_after": 1e300, "message": "rate limited"}))]);
+        let message = Message{
+            title: "Tip".to_string(),
+            text: "Borrow".to_string(),
+            html: "<b>Tip</b>\n\nBorrow".to_string(),
+        };
+
+        let start = Instant::now();
+        let result = Discord::new().send_text(&category(&format!("{}/webhook", stub.url)), &message).await;
+
+        assert!(result.is_err());
+        assert_eq!(stub.requests().len(), 1);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -6,13 +6,24 @@ use axum::{
 use serde_json::json;
 use std::fmt;
 
+use super::{
+    telegram::TelegramError,
+    validation::FieldError,
+};
+
 
 #[derive(Debug)]
 pub enum CustomError {
     BadRequest,
+    Unauthorized,
     NotFound,
     ServerError(String),
     OtherError(String),
+    Telegram(TelegramError),
+    Template(String),
+    Parse(String),
+    Conflict(String),
+    Validation(Vec<FieldError>),
 }
 
 impl fmt::Display for CustomError {
@@ -24,20 +35,41 @@ impl fmt::Display for CustomError {
         // is very similar to `println!`.
         match self{
             Self::BadRequest =>  write!(f, "Bad request"),
+            Self::Unauthorized =>  write!(f, "Unauthorized"),
             Self::NotFound =>  write!(f, "Not found"),
             Self::ServerError(e) =>  write!(f, "Server error: {}", e),
             Self::OtherError(e) =>  write!(f, "Server error: {}", e),
-            _ => write!(f, "Unknown error"),
+            Self::Telegram(e) =>  write!(f, "Telegram error: {}", e),
+            Self::Template(e) =>  write!(f, "Template error: {}", e),
+            Self::Parse(e) =>  write!(f, "Parse error: {}", e),
+            Self::Conflict(e) =>  write!(f, "Conflict: {}", e),
+            Self::Validation(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "Validation error: {}", errors.join(", "))
+            },
         }
     }
 }
+impl std::error::Error for CustomError {}
+
 impl IntoResponse for CustomError {
     fn into_response(self) -> Response {
         let (status, error_message) = match self {
             Self::ServerError(s) => (StatusCode::INTERNAL_SERVER_ERROR, s),
             Self::OtherError(s) => (StatusCode::INTERNAL_SERVER_ERROR, s),
+            Self::Telegram(e @ TelegramError::TooManyRequests(_)) => (StatusCode::SERVICE_UNAVAILABLE, e.to_string()),
+            Self::Telegram(e) => (StatusCode::BAD_GATEWAY, e.to_string()),
+            Self::Template(s) => (StatusCode::UNPROCESSABLE_ENTITY, s),
+            Self::Parse(s) => (StatusCode::BAD_REQUEST, s),
+            Self::Conflict(s) => (StatusCode::CONFLICT, s),
             Self::BadRequest=> (StatusCode::BAD_REQUEST, "Bad Request".to_string()),
+            Self::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
             Self::NotFound => (StatusCode::NOT_FOUND, "Not Found".to_string()),
+            Self::Validation(errors) => return (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({
+                "status": "error",
+                "message": "Validation error",
+                "errors": errors}))
+            ).into_response(),
         };
         (status, Json(json!({
             "status": "error",
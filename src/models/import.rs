@@ -0,0 +1,315 @@
+use std::collections::{HashMap, HashSet};
+use serde::{Serialize, Deserialize};
+use sqlx::sqlite::SqlitePool;
+use super::{
+    answer::{Answer, NewAnswer},
+    category::{Category, NewCategory},
+    poll::{Poll, NewPoll, NewPollWithAnswers},
+    tip::{Tip, NewTip, NewTipWithCategory},
+    validation::Validate,
+    error::CustomError,
+};
+
+/// Content to import. The categories are created before the tips and the
+/// polls, so these can use the categories of the same document.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Document{
+    #[serde(default)]
+    pub categories: Vec<NewCategory>,
+    #[serde(default)]
+    pub tips: Vec<NewTipWithCategory>,
+    #[serde(default)]
+    pub polls: Vec<NewPollWithAnswers>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Entity{
+    Category,
+    Tip,
+    Poll,
+    Answer,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status{
+    Created,
+    Updated,
+    Skipped,
+    Failed,
+}
+
+/// What happened with an item of the document. The index is the position
+/// of the item in its list.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImportedItem{
+    entity: Entity,
+    index: usize,
+    name: String,
+    status: Status,
+    id: Option<i64>,
+    reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Report{
+    created: usize,
+    updated: usize,
+    skipped: usize,
+    failed: usize,
+    items: Vec<ImportedItem>,
+}
+
+impl Report{
+    fn add(&mut self, entity: Entity, index: usize, name: &str, status: Status,
+            id: Option<i64>, reason: Option<String>){
+        match status{
+            Status::Created => self.created += 1,
+            Status::Updated => self.updated += 1,
+            Status::Skipped => self.skipped += 1,
+            Status::Failed => self.failed += 1,
+        }
+        self.items.push(ImportedItem{
+            entity,
+            index,
+            name: name.to_string(),
+            status,
+            id,
+            reason,
+        });
+    }
+
+    pub fn created(&mut self, entity: Entity, index: usize, name: &str, id: i64){
+        self.add(entity, index, name, Status::Created, Some(id), None);
+    }
+
+    pub fn updated(&mut self, entity: Entity, index: usize, name: &str, id: i64){
+        self.add(entity, index, name, Status::Updated, Some(id), None);
+    }
+
+    pub fn skipped(&mut self, entity: Entity, index: usize, name: &str, reason: &str){
+        self.add(entity, index, name, Status::Skipped, None, Some(reason.to_string()));
+    }
+
+    pub fn failed(&mut self, entity: Entity, index: usize, name: &str, reason: String){
+        self.add(entity, index, name, Status::Failed, None, Some(reason));
+    }
+
+    /// Lists the items in the order of the document
+    pub fn sort(&mut self){
+        self.items.sort_by_key(|item| (item.entity, item.index));
+    }
+}
+
+impl Document{
+    pub fn from_yaml(content: &str) -> Result<Self, CustomError>{
+        serde_yaml::from_str(content)
+            .map_err(|e| CustomError::Parse(e.to_string()))
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, CustomError>{
+        serde_json::from_str(content)
+            .map_err(|e| CustomError::Parse(e.to_string()))
+    }
+}
+
+/// The errors of the item, or whether its category doesn't exist
+fn check<V: Validate>(item: &V, category: Option<&str>, categories: &HashSet<String>) -> Result<(), String>{
+    item.validate().map_err(|e| e.to_string())?;
+    match category{
+        Some(category) if !categories.contains(category) => Err(format!("Category {} not found", category)),
+        _ => Ok(()),
+    }
+}
+
+/// Imports the document. Every item is checked before writing anything,
+/// the wrong ones are reported as failed and the ones already stored, with
+/// the same name, title or question in the same category, are skipped. The
+/// rest are created in a single transaction, so if one of them can't be
+/// stored nothing is.
+pub async fn import(pool: &SqlitePool, document: Document) -> Result<Report, CustomError>{
+    let mut ids: HashMap<String, i64> = Category::read_all(pool).await?
+        .into_iter()
+        .map(|category| (category.get_name().to_string(), category.get_id()))
+        .collect();
+    let names: HashMap<i64, String> = ids.iter()
+        .map(|(name, id)| (*id, name.clone()))
+        .collect();
+    let mut tips: HashSet<(String, String)> = Tip::read_all(pool).await?
+        .into_iter()
+        .filter_map(|tip| names.get(&tip.get_category_id())
+            .map(|name| (name.clone(), tip.get_title().to_string())))
+        .collect();
+    let mut polls: HashSet<(String, String)> = Poll::read_all(pool).await?
+        .into_iter()
+        .filter_map(|poll| names.get(&poll.get_category_id())
+            .map(|name| (name.clone(), poll.get_question().to_string())))
+        .collect();
+
+    let mut report = Report::default();
+    let mut categories: HashSet<String> = ids.keys().cloned().collect();
+    let mut new_categories = Vec::new();
+    for (index, category) in document.categories.into_iter().enumerate(){
+        let name = category.get_name().to_string();
+        if let Err(reason) = check(&category, None, &categories) {
+            report.failed(Entity::Category, index, &name, reason);
+        }else if !categories.insert(name.clone()) {
+            report.skipped(Entity::Category, index, &name, "The category already exists");
+        }else{
+            new_categories.push((index, category));
+        }
+    }
+    let mut new_tips = Vec::new();
+    for (index, tip) in document.tips.into_iter().enumerate(){
+        if let Err(reason) = check(&tip, Some(&tip.category), &categories) {
+            report.failed(Entity::Tip, index, &tip.title, reason);
+        }else if !tips.insert((tip.category.clone(), tip.title.clone())) {
+            report.skipped(Entity::Tip, index, &tip.title, "The tip already exists");
+        }else{
+            new_tips.push((index, tip));
+        }
+    }
+    let mut new_polls = Vec::new();
+    for (index, poll) in document.polls.into_iter().enumerate(){
+        if let Err(reason) = check(&poll, Some(&poll.category), &categories) {
+            report.failed(Entity::Poll, index, &poll.question, reason);
+        }else if !polls.insert((poll.category.clone(), poll.question.clone())) {
+            report.skipped(Entity::Poll, index, &poll.question, "The poll already exists");
+        }else{
+            new_polls.push((index, poll));
+        }
+    }
+
+    let mut tx = pool.begin()
+        .await
+        .map_err(|e| CustomError::ServerError(e.to_string()))?;
+    for (index, new_category) in new_categories{
+        let category = Category::create(&mut tx, new_category).await?;
+        ids.insert(category.get_name().to_string(), category.get_id());
+        report.created(Entity::Category, index, category.get_name(), category.get_id());
+    }
+    for (index, new_tip) in new_tips{
+        let mut tip = NewTip::new(ids[&new_tip.category], new_tip.title, new_tip.text);
+        tip.set_priority(new_tip.priority);
+        tip.set_publish_at(new_tip.publish_at);
+        let tip = Tip::create(&mut tx, tip).await?;
+        report.created(Entity::Tip, index, tip.get_title(), tip.get_id());
+    }
+    for (index, new_poll) in new_polls{
+        let mut poll = NewPoll::new(ids[&new_poll.category], new_poll.question);
+        poll.set_priority(new_poll.priority);
+        poll.set_publish_at(new_poll.publish_at);
+        let poll = Poll::create(&mut tx, poll).await?;
+        for answer in new_poll.answers{
+            Answer::create(&mut tx,
+                NewAnswer::new(poll.get_id(), answer.text, answer.isok)).await?;
+        }
+        report.created(Entity::Poll, index, poll.get_question(), poll.get_id());
+    }
+    tx.commit()
+        .await
+        .map_err(|e| CustomError::ServerError(e.to_string()))?;
+    report.sort();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests{
+    use serde_json::{json, Value};
+    use sqlx::query;
+    use crate::database;
+    use super::*;
+
+    fn document() -> Document{
+        serde_json::from_value(json!({
+            "categories": [
+                {"name": "Rust", "chat_id": "@rust", "thread_id": 0},
+                {"name": "Rust", "chat_id": "@rust2", "thread_id": 0},
+                {"name": "", "chat_id": "@none", "thread_id": 0},
+            ],
+            "tips": [
+                {"category": "Rust", "title": "Borrow", "text": "Only one &mut", "priority": 2},
+                {"category": "Rust", "title": "Borrow", "text": "Again"},
+                {"category": "Go", "title": "Goroutines", "text": "Cheap threads"},
+                {"category": "Rust", "title": "", "text": "No title"},
+            ],
+            "polls": [
+                {"category": "Rust", "question": "Which one moves?", "answers": [
+                    {"text": "String", "isok": true},
+                    {"text": "i32", "isok": false},
+                ]},
+                {"category": "Rust", "question": "Two right answers?", "answers": [
+                    {"text": "Yes", "isok": true},
+                    {"text": "Also yes", "isok": true},
+                ]},
+            ],
+        })).unwrap()
+    }
+
+    /// Status of each item, in the order of the document
+    fn statuses(report: &Report) -> Vec<(Value, Value, Value)>{
+        serde_json::to_value(report).unwrap()["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| (item["entity"].clone(), item["index"].clone(), item["status"].clone()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn reports_what_happened_with_each_item(){
+        let pool = database::memory().await;
+
+        let report = import(&pool, document()).await.unwrap();
+
+        assert_eq!(statuses(&report), vec![
+            (json!("category"), json!(0), json!("created")),
+            (json!("category"), json!(1), json!("skipped")),
+            (json!("category"), json!(2), json!("failed")),
+            (json!("tip"), json!(0), json!("created")),
+            (json!("tip"), json!(1), json!("skipped")),
+            (json!("tip"), json!(2), json!("failed")),
+            (json!("tip"), json!(3), json!("failed")),
+            (json!("poll"), json!(0), json!("created")),
+            (json!("poll"), json!(1), json!("failed")),
+        ]);
+        let items = serde_json::to_value(&report).unwrap()["items"].clone();
+        assert_eq!(items[5]["reason"], "Category Go not found");
+        let tips = Tip::read_all(&pool).await.unwrap();
+        assert_eq!(tips.len(), 1);
+        assert_eq!(Answer::read_all(&pool).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn skips_what_is_already_stored(){
+        let pool = database::memory().await;
+        import(&pool, document()).await.unwrap();
+
+        let report = import(&pool, document()).await.unwrap();
+
+        let report = serde_json::to_value(&report).unwrap();
+        assert_eq!(report["created"], 0);
+        assert_eq!(report["skipped"], 5);
+        assert_eq!(report["failed"], 4);
+        assert_eq!(Category::read_all(&pool).await.unwrap().len(), 1);
+        assert_eq!(Poll::read_all(&pool).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn stores_nothing_when_an_item_fails(){
+        let pool = database::memory().await;
+        query("CREATE TRIGGER fail_answer BEFORE INSERT ON answers
+               WHEN new.text = 'i32' BEGIN SELECT RAISE(ABORT, 'failed'); END;")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let result = import(&pool, document()).await;
+
+        assert!(matches!(result, Err(CustomError::ServerError(_))), "{:?}", result);
+        assert!(Category::read_all(&pool).await.unwrap().is_empty());
+        assert!(Tip::read_all(&pool).await.unwrap().is_empty());
+        assert!(Poll::read_all(&pool).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,341 @@
+use std::{fmt, str::FromStr};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+use sqlx::{sqlite::{Sqlite, SqlitePool, SqliteRow}, query, Executor, QueryBuilder, Row};
+use super::{
+    decode,
+    kind::Kind,
+    publisher::Receipt,
+    error::CustomError,
+};
+
+/// Where a job is in its life
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum State{
+    /// Waiting for the worker
+    Pending,
+    /// Being sent, nobody else can take it
+    Claimed,
+    Sent,
+    /// Waiting to be retried or cancelled
+    Failed,
+}
+
+/// States of the jobs that are not done, an item has one at most
+const OPEN: &str = "'pending', 'claimed', 'failed'";
+
+/// Error of the jobs found claimed when the server starts
+const INTERRUPTED: &str = "Interrupted while sending, it may have been sent";
+
+/// The publication of a tip or a poll. It is claimed before the item is
+/// sent and marked as sent together with the item, so an item is only sent
+/// twice if it is asked for.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Job{
+    id: i64,
+    kind: Kind,
+    item_id: i64,
+    category_id: i64,
+    state: State,
+    attempts: i64,
+    message_id: Option<String>,
+    error: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct JobFilter{
+    pub state: Option<State>,
+    pub kind: Option<Kind>,
+    pub category_id: Option<i64>,
+}
+
+impl State{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            Self::Pending => "pending",
+            Self::Claimed => "claimed",
+            Self::Sent => "sent",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for State{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for State{
+    type Err = CustomError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s{
+            "pending" => Ok(Self::Pending),
+            "claimed" => Ok(Self::Claimed),
+            "sent" => Ok(Self::Sent),
+            "failed" => Ok(Self::Failed),
+            _ => Err(CustomError::BadRequest),
+        }
+    }
+}
+
+/// Condition of the items of the table without a job that is not done, the
+/// ones that can be chosen to be published
+pub fn without_open_job(kind: Kind, table: &str) -> String{
+    format!("NOT EXISTS (SELECT 1 FROM publish_jobs WHERE publish_jobs.kind = '{}'
+             AND publish_jobs.item_id = {}.id AND publish_jobs.state IN ({}))",
+        kind.as_str(), table, OPEN)
+}
+
+impl Job{
+    /// Reads a job, a kind or a state that is not known is an error, the job
+    /// can't be sent or changed
+    fn from_row(row: SqliteRow) -> Result<Self, sqlx::Error>{
+        Ok(Self{
+            id: row.get("id"),
+            kind: decode(&row, "kind")?,
+            item_id: row.get("item_id"),
+            category_id: row.get("category_id"),
+            state: decode(&row, "state")?,
+            attempts: row.get("attempts"),
+            message_id: row.get("message_id"),
+            error: row.get("error"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        })
+    }
+
+    pub fn get_id(&self) -> i64{
+        self.id
+    }
+
+    pub fn get_kind(&self) -> Kind{
+        self.kind
+    }
+
+    pub fn get_item_id(&self) -> i64{
+        self.item_id
+    }
+
+    /// Creates the job already claimed, to send the item right away. It
+    /// fails if the item has another job that is not done.
+    pub async fn start(pool: &SqlitePool, kind: Kind, item_id: i64, category_id: i64) -> Result<Job, CustomError>{
+        let sql = "INSERT INTO publish_jobs (kind, item_id, category_id, state, attempts,
+                   created_at, updated_at)
+                   VALUES ($1, $2, $3, 'claimed', 1, $4, $4)
+                   ON CONFLICT DO NOTHING RETURNING *;";
+        query(sql)
+            .bind(kind.as_str())
+            .bind(item_id)
+            .bind(category_id)
+            .bind(Utc::now())
+            .try_map(Self::from_row)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?
+            .ok_or_else(|| CustomError::Conflict(format!(
+                "The {} {} has a job that is not done, retry or cancel it", kind, item_id)))
+    }
+
+    /// Claims the oldest pending job. It is a single statement, so two
+    /// workers can't claim the same job.
+    pub async fn claim(pool: &SqlitePool) -> Result<Option<Job>, CustomError>{
+        let sql = "UPDATE publish_jobs SET state = 'claimed', attempts = attempts + 1,
+                   updated_at = $1
+                   WHERE id = (SELECT id FROM publish_jobs WHERE state = 'pending'
+                               ORDER BY id LIMIT 1)
+                   AND state = 'pending' RETURNING *;";
+        query(sql)
+            .bind(Utc::now())
+            .try_map(Self::from_row)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// Marks the claimed job as sent, in the transaction that marks the item
+    /// as published
+    pub async fn finish<'e, E: Executor<'e, Database = Sqlite>>(executor: E, id: i64, receipt: &Receipt)
+            -> Result<Job, CustomError>{
+        let sql = "UPDATE publish_jobs SET state = 'sent', message_id = $2, error = NULL,
+                   updated_at = $3 WHERE id = $1 AND state = 'claimed' RETURNING *;";
+        query(sql)
+            .bind(id)
+            .bind(&receipt.message_id)
+            .bind(Utc::now())
+            .try_map(Self::from_row)
+            .fetch_one(executor)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// Marks the claimed job as failed, it waits to be retried or cancelled
+    pub async fn fail(pool: &SqlitePool, id: i64, error: &str) -> Result<Job, CustomError>{
+        let sql = "UPDATE publish_jobs SET state = 'failed', error = $2, updated_at = $3
+                   WHERE id = $1 AND state = 'claimed' RETURNING *;";
+        query(sql)
+            .bind(id)
+            .bind(error)
+            .bind(Utc::now())
+            .try_map(Self::from_row)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// Fails the jobs claimed longer than the lease ago, left by a process
+    /// that stopped while sending them. They are not sent again on their own,
+    /// they may have been sent.
+    pub async fn interrupt(pool: &SqlitePool, lease: std::time::Duration) -> Result<u64, CustomError>{
+        let now = Utc::now();
+        let expired = now - Duration::from_std(lease)
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        let sql = "UPDATE publish_jobs SET state = 'failed', error = $1, updated_at = $2
+                   WHERE state = 'claimed' AND datetime(updated_at) <= datetime($3);";
+        query(sql)
+            .bind(INTERRUPTED)
+            .bind(now)
+            .bind(expired)
+            .execute(pool)
+            .await
+            .map(|result| result.rows_affected())
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    pub async fn read(pool: &SqlitePool, id: i64) -> Result<Option<Job>, CustomError>{
+        let sql = "SELECT * FROM publish_jobs WHERE id = $1";
+        query(sql)
+            .bind(id)
+            .try_map(Self::from_row)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    pub async fn search(pool: &SqlitePool, filter: &JobFilter) -> Result<Vec<Job>, CustomError>{
+        let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "SELECT * FROM publish_jobs WHERE 1 = 1");
+        if let Some(state) = filter.state {
+            builder.push(" AND state = ").push_bind(state.as_str());
+        }
+        if let Some(kind) = filter.kind {
+            builder.push(" AND kind = ").push_bind(kind.as_str());
+        }
+        if let Some(category_id) = filter.category_id {
+            builder.push(" AND category_id = ").push_bind(category_id);
+        }
+        builder.push(" ORDER BY id DESC");
+        builder.build()
+            .try_map(Self::from_row)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// Puts a failed job back to pending, for the worker to send it again
+    pub async fn retry(pool: &SqlitePool, id: i64) -> Result<Job, CustomError>{
+        let sql = "UPDATE publish_jobs SET state = 'pending', updated_at = $2
+                   WHERE id = $1 AND state = 'failed' RETURNING *;";
+        let job = query(sql)
+            .bind(id)
+            .bind(Utc::now())
+            .try_map(Self::from_row)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        match job{
+            Some(job) => Ok(job),
+            None => Err(Self::not_in(pool, id, "failed").await),
+        }
+    }
+
+    /// Deletes a pending or failed job, the item goes back to the queue if
+    /// it was not published
+    pub async fn cancel(pool: &SqlitePool, id: i64) -> Result<Job, CustomError>{
+        let sql = "DELETE FROM publish_jobs WHERE id = $1 AND state IN ('pending', 'failed')
+                   RETURNING *;";
+        let job = query(sql)
+            .bind(id)
+            .try_map(Self::from_row)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        match job{
+            Some(job) => Ok(job),
+            None => Err(Self::not_in(pool, id, "pending or failed").await),
+        }
+    }
+
+    /// Why the job couldn't be changed, it doesn't exist or it is not in the
+    /// expected state
+    async fn not_in(pool: &SqlitePool, id: i64, expected: &str) -> CustomError{
+        match Self::read(pool, id).await{
+            Ok(Some(job)) => CustomError::Conflict(format!(
+                "The job {} is {}, it has to be {}", id, job.state, expected)),
+            Ok(None) => CustomError::NotFound,
+            Err(e) => e,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::database;
+    use super::*;
+
+    const LEASE: std::time::Duration = std::time::Duration::from_secs(120);
+
+    #[tokio::test]
+    async fn interrupts_only_the_jobs_claimed_longer_than_the_lease(){
+        let pool = database::memory().await;
+        let stale = Job::start(&pool, Kind::Tip, 1, 1).await.unwrap();
+        let sending = Job::start(&pool, Kind::Tip, 2, 1).await.unwrap();
+        query("UPDATE publish_jobs SET updated_at = $2 WHERE id = $1")
+            .bind(stale.get_id())
+            .bind(Utc::now() - Duration::minutes(5))
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(Job::interrupt(&pool, LEASE).await.unwrap(), 1);
+
+        let stale = Job::read(&pool, stale.get_id()).await.unwrap().unwrap();
+        assert_eq!(stale.state, State::Failed);
+        assert_eq!(stale.error.as_deref(), Some(INTERRUPTED));
+        let sending = Job::read(&pool, sending.get_id()).await.unwrap().unwrap();
+        assert_eq!(sending.state, State::Claimed);
+    }
+
+    #[tokio::test]
+    async fn an_unknown_kind_is_an_error(){
+        let pool = database::memory().await;
+        let job = Job::start(&pool, Kind::Poll, 1, 1).await.unwrap();
+        query("UPDATE publish_jobs SET kind = 'video' WHERE id = $1")
+            .bind(job.get_id())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        match Job::read(&pool, job.get_id()).await{
+            Err(CustomError::ServerError(e)) => assert!(e.contains("unknown kind video"), "{}", e),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+}
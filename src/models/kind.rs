@@ -0,0 +1,38 @@
+use std::{fmt, str::FromStr};
+use serde::{Serialize, Deserialize};
+use super::error::CustomError;
+
+/// Type of content that can be published
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind{
+    Tip,
+    Poll,
+}
+
+impl Kind{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            Self::Tip => "tip",
+            Self::Poll => "poll",
+        }
+    }
+}
+
+impl fmt::Display for Kind{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Kind{
+    type Err = CustomError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s{
+            "tip" => Ok(Self::Tip),
+            "poll" => Ok(Self::Poll),
+            _ => Err(CustomError::BadRequest),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use serde::{Serialize, Deserialize};
+use sqlx::{sqlite::{Sqlite, SqlitePool, SqliteRow}, QueryBuilder, Row};
+use super::{
+    validation::Errors,
+    error::CustomError,
+};
+
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 500;
+
+/// Page, filters and order asked for in the query of a list endpoint
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ListParams{
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub offset: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<bool>,
+    /// Text the searched columns have to contain, ignoring the case
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+    /// Column to sort by, descending when it starts with `-`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+}
+
+/// How a table can be listed
+pub struct Columns{
+    pub table: &'static str,
+    /// Columns looked up with `q`
+    pub search: &'static [&'static str],
+    pub sort: &'static [&'static str],
+    /// Whether the table has the `category_id` and `published` columns
+    pub categorized: bool,
+}
+
+/// A page of a list, with the link to the next one if there are more items
+#[derive(Debug, Serialize)]
+pub struct Page<T>{
+    pub items: Vec<T>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub next: Option<String>,
+}
+
+impl<T> Page<T>{
+    pub fn new(items: Vec<T>, total: i64, params: &ListParams, path: &str) -> Self{
+        let limit = params.get_limit();
+        let offset = params.offset.max(0);
+        let next = if offset + limit < total {
+            let next = ListParams{
+                limit: Some(limit),
+                offset: offset + limit,
+                ..params.clone()
+            };
+            serde_urlencoded::to_string(&next)
+                .ok()
+                .map(|query| format!("{}?{}", path, query))
+        }else{
+            None
+        };
+        Self{
+            items,
+            total,
+            limit,
+            offset,
+            next,
+        }
+    }
+}
+
+impl ListParams{
+    pub fn get_limit(&self) -> i64{
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    fn check(&self, columns: &Columns) -> Result<(), CustomError>{
+        let mut errors = Errors::default();
+        if !columns.categorized {
+            if self.category_id.is_some() {
+                errors.add("category_id", format!("can't filter {} by category", columns.table));
+            }
+            if self.published.is_some() {
+                errors.add("published", format!("can't filter {} by published", columns.table));
+            }
+        }
+        if let Some(sort) = &self.sort{
+            let column = sort.strip_prefix('-').unwrap_or(sort);
+            if !columns.sort.contains(&column) {
+                errors.add("sort", format!("can be one of {}", columns.sort.join(", ")));
+            }
+        }
+        errors.into_result()
+    }
+
+    fn push_filters(&self, builder: &mut QueryBuilder<'_, Sqlite>, columns: &Columns){
+        builder.push(" WHERE 1 = 1");
+        if let Some(category_id) = self.category_id{
+            builder.push(" AND category_id = ").push_bind(category_id);
+        }
+        if let Some(published) = self.published{
+            builder.push(" AND published = ").push_bind(published);
+        }
+        if let Some(q) = self.q.as_deref().map(str::trim).filter(|q| !q.is_empty()){
+            builder.push(" AND (");
+            for (index, column) in columns.search.iter().enumerate(){
+                if index > 0 {
+                    builder.push(" OR ");
+                }
+                builder.push(format!("instr(lower({}), lower(", column))
+                    .push_bind(q.to_string())
+                    .push(")) > 0");
+            }
+            builder.push(")");
+        }
+    }
+
+    /// Reads the page of the table and how many items match the filters
+    pub async fn read<T, F>(&self, pool: &SqlitePool, columns: &Columns, from_row: F)
+            -> Result<(Vec<T>, i64), CustomError>
+    where F: FnMut(SqliteRow) -> Result<T, sqlx::Error> + Send, T: Send + Unpin{
+        self.check(columns)?;
+        let mut count = QueryBuilder::new(format!("SELECT COUNT(*) FROM {}", columns.table));
+        self.push_filters(&mut count, columns);
+        let total: i64 = count.build()
+            .fetch_one(pool)
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?
+            .get(0);
+
+        let mut select = QueryBuilder::new(format!("SELECT * FROM {}", columns.table));
+        self.push_filters(&mut select, columns);
+        match self.sort.as_deref(){
+            Some(sort) => match sort.strip_prefix('-'){
+                Some(column) => select.push(format!(" ORDER BY {} DESC, id DESC", column)),
+                None => select.push(format!(" ORDER BY {}, id", sort)),
+            },
+            None => select.push(" ORDER BY id"),
+        };
+        select.push(" LIMIT ").push_bind(self.get_limit())
+            .push(" OFFSET ").push_bind(self.offset.max(0));
+        let items = select.build()
+            .try_map(from_row)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        Ok((items, total))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use sqlx::query;
+    use crate::database;
+    use super::*;
+
+    const TIPS: Columns = Columns{
+        table: "tips",
+        search: &["title", "text"],
+        sort: &["id", "title"],
+        categorized: true,
+    };
+
+    const CATEGORIES: Columns = Columns{
+        table: "categories",
+        search: &["name"],
+        sort: &["id"],
+        categorized: false,
+    };
+
+    /// Five tips, the even ones published and in the second category
+    async fn with_tips() -> SqlitePool{
+        let pool = database::memory().await;
+        for (id, title) in ["Borrow", "Move", "Clone", "Copy", "Drop"].iter().enumerate(){
+            let id = id as i64 + 1;
+            query("INSERT INTO tips (category_id, title, text, published) VALUES ($1, $2, '', $3)")
+                .bind(2 - id % 2)
+                .bind(title)
+                .bind(id % 2 == 0)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        pool
+    }
+
+    async fn ids(pool: &SqlitePool, params: &ListParams) -> Result<(Vec<i64>, i64), CustomError>{
+        params.read(pool, &TIPS, |row| row.try_get("id")).await
+    }
+
+    fn query_params(query: &str) -> ListParams{
+        serde_urlencoded::from_str(query).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reads_a_page(){
+        let pool = with_tips().await;
+
+        assert_eq!(ids(&pool, &query_params("limit=2&offset=2")).await.unwrap(), (vec![3, 4], 5));
+        assert_eq!(ids(&pool, &query_params("limit=2&offset=4")).await.unwrap(), (vec![5], 5));
+        assert_eq!(ids(&pool, &query_params("limit=0&offset=-3")).await.unwrap(), (vec![1], 5));
+    }
+
+    #[tokio::test]
+    async fn filters_the_items(){
+        let pool = with_tips().await;
+
+        assert_eq!(ids(&pool, &query_params("category_id=2")).await.unwrap(), (vec![2, 4], 2));
+        assert_eq!(ids(&pool, &query_params("published=false")).await.unwrap(), (vec![1, 3, 5], 3));
+        assert_eq!(ids(&pool, &query_params("q=CO")).await.unwrap(), (vec![4], 1));
+        assert_eq!(ids(&pool, &query_params("q=o&category_id=1&limit=1")).await.unwrap(), (vec![1], 3));
+        let result = query_params("category_id=1")
+            .read(&pool, &CATEGORIES, |row| row.try_get::<i64, _>("id"))
+            .await;
+        assert!(matches!(result, Err(CustomError::Validation(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn sorts_by_the_allowed_columns(){
+        let pool = with_tips().await;
+
+        assert_eq!(ids(&pool, &query_params("sort=title")).await.unwrap().0, vec![1, 3, 4, 5, 2]);
+        assert_eq!(ids(&pool, &query_params("sort=-id")).await.unwrap().0, vec![5, 4, 3, 2, 1]);
+        for sort in ["--id", "text", "id;DROP TABLE tips"]{
+            let result = ids(&pool, &ListParams{sort: Some(sort.to_string()), ..ListParams::default()}).await;
+            assert!(matches!(result, Err(CustomError::Validation(_))), "{}: {:?}", sort, result);
+        }
+    }
+
+    #[test]
+    fn links_the_next_page_with_the_same_filters(){
+        let params = query_params("limit=2&category_id=1&q=rust&sort=-id");
+
+        let page = Page::new(vec![1, 2], 5, &params, "/api/v1/tips");
+        assert_eq!(page.next.as_deref(),
+            Some("/api/v1/tips?limit=2&offset=2&category_id=1&q=rust&sort=-id"));
+        let page = Page::new(vec![5], 5, &ListParams{offset: 4, ..params}, "/api/v1/tips");
+        assert_eq!(page.next, None);
+    }
+}
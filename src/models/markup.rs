@@ -0,0 +1,171 @@
+//! Helpers to convert the Telegram flavoured HTML of the messages to the
+//! formats used by other platforms.
+
+/// Builds a valid hashtag from a category name, `rust lang` → `#RustLang`
+pub fn hashtag(name: &str) -> String{
+    let words: Vec<&str> = name
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.len() == 1 {
+        return format!("#{}", words[0]);
+    }
+    let tag: String = words.iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next(){
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect();
+    format!("#{}", tag)
+}
+
+/// Replaces the HTML entities Telegram accepts by their characters
+pub fn unescape(text: &str) -> String{
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// An HTML tag found while parsing
+struct Tag<'a>{
+    name: String,
+    closing: bool,
+    source: &'a str,
+}
+
+impl<'a> Tag<'a>{
+    fn parse(source: &'a str) -> Self{
+        let inner = source.trim_start_matches('<').trim_end_matches('>').trim();
+        let closing = inner.starts_with('/');
+        let name = inner.trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+        Self{ name, closing, source }
+    }
+
+    /// Value of an attribute, like the `href` of a link
+    fn attribute(&self, attribute: &str) -> Option<String>{
+        let start = self.source.find(&format!("{}=", attribute))? + attribute.len() + 1;
+        let rest = &self.source[start..];
+        let quote = rest.chars().next()?;
+        if quote == '"' || quote == '\'' {
+            rest[1..].split(quote).next().map(unescape)
+        }else{
+            rest.split(|c: char| c.is_whitespace() || c == '>').next().map(unescape)
+        }
+    }
+}
+
+/// A piece of the HTML, a tag or the text between tags
+enum Node<'a>{
+    Tag(Tag<'a>),
+    Text(&'a str),
+}
+
+/// Splits the HTML in tags and text
+fn parse(html: &str) -> Vec<Node<'_>>{
+    let mut nodes = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        nodes.push(Node::Text(&rest[..start]));
+        match rest[start..].find('>') {
+            Some(end) => {
+                nodes.push(Node::Tag(Tag::parse(&rest[start..start + end + 1])));
+                rest = &rest[start + end + 1..];
+            },
+            None => {
+                nodes.push(Node::Text(&rest[start..]));
+                rest = "";
+            }
+        }
+    }
+    nodes.push(Node::Text(rest));
+    nodes
+}
+
+/// Converts the HTML of a message to plain text. Links keep their address
+/// between parentheses when it is not the text of the link.
+pub fn html_to_text(html: &str) -> String{
+    let mut output = String::new();
+    let mut links: Vec<(Option<String>, usize)> = Vec::new();
+    for node in parse(html){
+        match node{
+            Node::Text(text) => output.push_str(&unescape(text)),
+            Node::Tag(tag) => match (tag.name.as_str(), tag.closing) {
+                ("br", _) => output.push('\n'),
+                ("a", false) => links.push((tag.attribute("href"), output.len())),
+                ("a", true) => {
+                    if let Some((Some(href), start)) = links.pop() {
+                        if output[start..].trim() != href {
+                            output.push_str(&format!(" ({})", href));
+                        }
+                    }
+                },
+                _ => {},
+            },
+        }
+    }
+    output.trim().to_string()
+}
+
+/// Converts the HTML of a message to the markdown used by Discord
+pub fn html_to_markdown(html: &str) -> String{
+    let mut output = String::new();
+    let mut links: Vec<Option<String>> = Vec::new();
+    let mut spoilers: Vec<bool> = Vec::new();
+    // Inside a block of code the `code` tags don't need their own marks
+    let mut in_pre = false;
+    for node in parse(html){
+        match node{
+            Node::Text(text) => output.push_str(&unescape(text)),
+            Node::Tag(tag) => match (tag.name.as_str(), tag.closing) {
+                ("br", _) => output.push('\n'),
+                ("b" | "strong", _) => output.push_str("**"),
+                ("i" | "em", _) => output.push('*'),
+                ("u" | "ins", _) => output.push_str("__"),
+                ("s" | "strike" | "del", _) => output.push_str("~~"),
+                ("tg-spoiler", _) => output.push_str("||"),
+                ("span", false) => {
+                    let spoiler = tag.attribute("class").as_deref() == Some("tg-spoiler");
+                    if spoiler {
+                        output.push_str("||");
+                    }
+                    spoilers.push(spoiler);
+                },
+                ("span", true) if spoilers.pop().unwrap_or(false) => output.push_str("||"),
+                ("code", _) if !in_pre => output.push('`'),
+                ("pre", false) => {
+                    in_pre = true;
+                    output.push_str("```\n");
+                },
+                ("pre", true) => {
+                    in_pre = false;
+                    output.push_str("\n```");
+                },
+                ("blockquote", false) => output.push_str("> "),
+                ("a", false) => {
+                    let href = tag.attribute("href");
+                    if href.is_some() {
+                        output.push('[');
+                    }
+                    links.push(href);
+                },
+                ("a", true) => {
+                    if let Some(Some(href)) = links.pop() {
+                        output.push_str(&format!("]({})", href));
+                    }
+                },
+                _ => {},
+            },
+        }
+    }
+    output.trim().to_string()
+}
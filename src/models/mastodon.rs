@@ -0,0 +1,260 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::{Client, Response};
+use serde_json::{json, Value};
+use tracing::{info, error};
+
+use super::{
+    category::Category,
+    markup,
+    publisher::{Publisher, Message, Quiz, Receipt},
+    validation::Errors,
+    error::CustomError,
+};
+
+/// Maximum length of a status in a default Mastodon instance
+const MAX_LENGTH: usize = 500;
+/// Options of a poll in a default Mastodon instance
+const MAX_OPTIONS: usize = 4;
+/// Longest option of a poll in a default Mastodon instance
+const MAX_OPTION_LENGTH: usize = 50;
+/// Maximum time to establish the connection with the instance
+const CONNECT_TIMEOUT: u64 = 10;
+/// Maximum time for the whole request
+const TIMEOUT: u64 = 30;
+/// Seconds a poll stays open
+const POLL_EXPIRES_IN: u64 = 24 * 60 * 60;
+
+/// Client for the Mastodon API, also implemented by other fediverse
+/// servers. The instance and the access token are set in each category.
+#[derive(Debug)]
+pub struct Mastodon {
+    client: Client,
+}
+
+/// Cuts the text so it fits in a status. When the text ends with the
+/// hashtag, the body is cut and the hashtag is kept.
+fn truncate(text: &str, hashtag: &str) -> String{
+    if text.chars().count() <= MAX_LENGTH {
+        return text.to_string();
+    }
+    let (body, tail) = match text.strip_suffix(hashtag){
+        Some(body) => {
+            let body = body.trim_end();
+            (body, &text[body.len()..])
+        },
+        None => (text, ""),
+    };
+    let room = MAX_LENGTH.saturating_sub(tail.chars().count() + 1);
+    let mut truncated: String = body.chars().take(room).collect();
+    truncated.push('…');
+    truncated.push_str(tail);
+    truncated
+}
+
+/// Checks the options fit in a poll, the instance rejects them otherwise
+fn check_options(options: &[String]) -> Result<(), CustomError>{
+    let mut errors = Errors::default();
+    if options.len() > MAX_OPTIONS {
+        errors.add("answers", format!("a poll in Mastodon can't have more than {} answers",
+            MAX_OPTIONS));
+    }
+    for (index, option) in options.iter().enumerate(){
+        errors.max_length(&format!("answers[{}].text", index), option, MAX_OPTION_LENGTH);
+    }
+    errors.into_result()
+}
+
+impl Mastodon {
+    pub fn new() -> Self{
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT))
+            .timeout(Duration::from_secs(TIMEOUT))
+            .build()
+            .expect("Can't build the HTTP client");
+        Self {
+            client,
+        }
+    }
+
+    fn instance<'a>(&self, category: &'a Category) -> Result<(&'a str, &'a str), CustomError>{
+        match (category.get_mastodon_url(), category.get_mastodon_token()){
+            (Some(url), Some(token)) => Ok((url.trim_end_matches('/'), token)),
+            _ => Err(CustomError::OtherError(format!(
+                "Category {} has no Mastodon instance configured",
+                category.get_name()))),
+        }
+    }
+
+    async fn post_status(&self, category: &Category, status: Value) -> Result<String, CustomError>{
+        let (url, token) = self.instance(category)?;
+        let url = format!("{}/api/v1/statuses", url);
+        tracing::debug!("Status: {}", &status);
+        match self.client
+            .post(url)
+            .bearer_auth(token)
+            .json(&status)
+            .send()
+            .await{
+                Ok(response) => Self::read_id(response).await,
+                Err(error) => {
+                    error!("Could not publish in Mastodon: {}", error);
+                    Err(CustomError::OtherError(error.to_string()))
+                },
+            }
+    }
+
+    /// Gets the id of the created status, or the error returned by the
+    /// instance
+    async fn read_id(response: Response) -> Result<String, CustomError>{
+        let status = response.status();
+        let body: Value = response.json()
+            .await
+            .unwrap_or(Value::Null);
+        if status.is_success() {
+            if let Some(id) = body.get("id").and_then(|id| id.as_str()) {
+                info!("Published in Mastodon: {}", id);
+                return Ok(id.to_string());
+            }
+        }
+        let message = body.get("error")
+            .and_then(|message| message.as_str())
+            .unwrap_or("unexpected response");
+        error!("Could not publish in Mastodon: {} {}", status, message);
+        Err(CustomError::OtherError(format!("Mastodon: {} {}", status, message)))
+    }
+}
+
+#[async_trait]
+impl Publisher for Mastodon{
+    async fn send_text(&self, category: &Category, message: &Message) -> Result<Receipt, CustomError>{
+        let status = self.preview_text(category, message)?;
+        let id = self.post_status(category, status).await?;
+        Ok(Receipt{
+            message_id: Some(id),
+        })
+    }
+
+    async fn send_quiz(&self, category: &Category, quiz: &Quiz) -> Result<Receipt, CustomError>{
+        let status = self.preview_quiz(category, quiz)?;
+        let id = self.post_status(category, status).await?;
+        Ok(Receipt{
+            message_id: Some(id),
+        })
+    }
+
+    fn preview_text(&self, category: &Category, message: &Message) -> Result<Value, CustomError>{
+        let hashtag = markup::hashtag(category.get_name());
+        Ok(json!({
+            "status": truncate(&markup::html_to_text(&message.html), &hashtag),
+            "visibility": "public",
+        }))
+    }
+
+    fn preview_quiz(&self, category: &Category, quiz: &Quiz) -> Result<Value, CustomError>{
+        let options: Vec<String> = quiz.options.iter()
+            .map(|option| markup::html_to_text(option))
+            .collect();
+        check_options(&options)?;
+        let hashtag = markup::hashtag(category.get_name());
+        Ok(json!({
+            "status": truncate(&markup::html_to_text(&quiz.question), &hashtag),
+            "visibility": "public",
+            "poll": {
+                "options": options,
+                "expires_in": POLL_EXPIRES_IN,
+                "multiple": false,
+            },
+        }))
+    }
+
+    fn max_send_time(&self) -> Duration{
+        Duration::from_secs(TIMEOUT)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use serde_json::json;
+    use crate::stub::Stub;
+    use super::*;
+
+    fn category(url: &str) -> Category{
+        serde_json::from_value(json!({
+            "id": 1,
+            "name": "Rust",
+            "chat_id": "",
+            "thread_id": 0,
+            "backend": "mastodon",
+            "mastodon_url": url,
+            "mastodon_token": "secret",
+        })).unwrap()
+    }
+
+    fn message(text: &str) -> Message{
+        Message{
+            title: "Tip".to_string(),
+            text: text.to_string(),
+            html: format!("<b>Tip</b>\n\n{}\n\n#Rust", text),
+        }
+    }
+
+    #[tokio::test]
+    async fn posts_the_tip_as_a_status(){
+        let stub = Stub::start(vec![(200, json!({"id": "42"}))]);
+
+        let receipt = Mastodon::new().send_text(&category(&stub.url), &message("Borrow")).await.unwrap();
+
+        assert_eq!(receipt.message_id.as_deref(), Some("42"));
+        let requests = stub.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/api/v1/statuses");
+        assert_eq!(requests[0].authorization.as_deref(), Some("Bearer secret"));
+        assert_eq!(requests[0].body["status"], "Tip\n\nBorrow\n\n#Rust");
+    }
+
+    #[tokio::test]
+    async fn cuts_a_long_tip_and_keeps_the_hashtag(){
+        let stub = Stub::start(vec![(200, json!({"id": "42"}))]);
+
+        Mastodon::new().send_text(&category(&stub.url), &message(&"a".repeat(600))).await.unwrap();
+
+        let status = stub.requests()[0].body["status"].as_str().unwrap().to_string();
+        assert_eq!(status.chars().count(), MAX_LENGTH);
+        assert!(status.ends_with("a…\n\n#Rust"));
+    }
+
+    #[tokio::test]
+    async fn rejects_a_poll_over_the_limits_without_sending_it(){
+        let stub = Stub::start(vec![(200, json!({"id": "42"}))]);
+        let quiz = Quiz{
+            question: "Which one?".to_string(),
+            options: vec!["a".to_string(), "b".to_string(), "c".to_string(),
+                "d".to_string(), "e".repeat(51)],
+            correct_option_id: 0,
+        };
+
+        let result = Mastodon::new().send_quiz(&category(&stub.url), &quiz).await;
+
+        match result{
+            Err(CustomError::Validation(errors)) => {
+                let fields: Vec<&str> = errors.iter().map(|error| error.field.as_str()).collect();
+                assert_eq!(fields, vec!["answers", "answers[4].text"]);
+            },
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert!(stub.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn returns_the_error_of_the_instance(){
+        let stub = Stub::start(vec![(422, json!({"error": "Validation failed"}))]);
+
+        let result = Mastodon::new().send_text(&category(&stub.url), &message("Borrow")).await;
+
+        match result{
+            Err(CustomError::OtherError(message)) => assert!(message.contains("Validation failed")),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+}
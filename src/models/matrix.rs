@@ -0,0 +1,238 @@
+use std::{sync::atomic::{AtomicU64, Ordering}, time::Duration};
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::{Client, Response, Url};
+use serde_json::{json, Value};
+use tracing::{info, error};
+
+use super::{
+    category::Category,
+    markup,
+    publisher::{Publisher, Message, Quiz, Receipt},
+    error::CustomError,
+};
+
+/// Maximum time to establish the connection with the homeserver
+const CONNECT_TIMEOUT: u64 = 10;
+/// Maximum time for the whole request
+const TIMEOUT: u64 = 30;
+
+/// Client for the Matrix client-server API. The homeserver, the room and
+/// the access token are set in each category.
+#[derive(Debug)]
+pub struct Matrix {
+    client: Client,
+    transactions: AtomicU64,
+}
+
+impl Matrix {
+    pub fn new() -> Self{
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT))
+            .timeout(Duration::from_secs(TIMEOUT))
+            .build()
+            .expect("Can't build the HTTP client");
+        Self {
+            client,
+            transactions: AtomicU64::new(0),
+        }
+    }
+
+    /// Builds a transaction id that is not repeated, so the homeserver
+    /// doesn't take a new event for a retry of a previous one
+    fn transaction_id(&self) -> String{
+        format!("publirs-{}-{}",
+            Utc::now().timestamp_millis(),
+            self.transactions.fetch_add(1, Ordering::Relaxed))
+    }
+
+    async fn send_event(&self, category: &Category, event_type: &str, content: Value) -> Result<String, CustomError>{
+        let (homeserver, room_id, token) = match (
+                category.get_matrix_homeserver(),
+                category.get_matrix_room_id(),
+                category.get_matrix_token()){
+            (Some(homeserver), Some(room_id), Some(token)) => (homeserver, room_id, token),
+            _ => return Err(CustomError::OtherError(format!(
+                "Category {} has no Matrix room configured",
+                category.get_name()))),
+        };
+        let mut url = Url::parse(homeserver)
+            .map_err(|e| CustomError::OtherError(format!("Matrix homeserver: {}", e)))?;
+        url.path_segments_mut()
+            .map_err(|_| CustomError::OtherError("Matrix homeserver is not valid".to_string()))?
+            .pop_if_empty()
+            .extend(&["_matrix", "client", "v3", "rooms", room_id, "send",
+                event_type, &self.transaction_id()]);
+        tracing::debug!("Event: {}", &content);
+        match self.client
+            .put(url)
+            .bearer_auth(token)
+            .json(&content)
+            .send()
+            .await{
+                Ok(response) => Self::read_event_id(response).await,
+                Err(error) => {
+                    error!("Could not send the event to Matrix: {}", error);
+                    Err(CustomError::OtherError(error.to_string()))
+                },
+            }
+    }
+
+    /// Gets the id of the event sent, or the error returned by the
+    /// homeserver
+    async fn read_event_id(response: Response) -> Result<String, CustomError>{
+        let status = response.status();
+        let body: Value = response.json()
+            .await
+            .unwrap_or(Value::Null);
+        if status.is_success() {
+            if let Some(event_id) = body.get("event_id").and_then(|id| id.as_str()) {
+                info!("Event sent to Matrix: {}", event_id);
+                return Ok(event_id.to_string());
+            }
+        }
+        let errcode = body.get("errcode")
+            .and_then(|errcode| errcode.as_str())
+            .unwrap_or("M_UNKNOWN");
+        let message = body.get("error")
+            .and_then(|message| message.as_str())
+            .unwrap_or("unexpected response");
+        error!("Could not send the event to Matrix: {} {} {}", status, errcode, message);
+        Err(CustomError::OtherError(format!("Matrix: {} {} {}", status, errcode, message)))
+    }
+}
+
+#[async_trait]
+impl Publisher for Matrix{
+    async fn send_text(&self, category: &Category, message: &Message) -> Result<Receipt, CustomError>{
+        let content = self.preview_text(category, message)?;
+        let event_id = self.send_event(category, "m.room.message", content).await?;
+        Ok(Receipt{
+            message_id: Some(event_id),
+        })
+    }
+
+    async fn send_quiz(&self, category: &Category, quiz: &Quiz) -> Result<Receipt, CustomError>{
+        let content = self.preview_quiz(category, quiz)?;
+        let event_id = self.send_event(category, "m.poll.start", content).await?;
+        Ok(Receipt{
+            message_id: Some(event_id),
+        })
+    }
+
+    fn preview_text(&self, _category: &Category, message: &Message) -> Result<Value, CustomError>{
+        Ok(json!({
+            "msgtype": "m.text",
+            "body": markup::html_to_text(&message.html),
+            "format": "org.matrix.custom.html",
+            "formatted_body": message.html.replace('\n', "<br>"),
+        }))
+    }
+
+    fn preview_quiz(&self, _category: &Category, quiz: &Quiz) -> Result<Value, CustomError>{
+        let question = markup::html_to_text(&quiz.question);
+        let options: Vec<String> = quiz.options.iter()
+            .map(|option| markup::html_to_text(option))
+            .collect();
+        let answers: Vec<Value> = options.iter()
+            .enumerate()
+            .map(|(position, option)| json!({
+                "m.id": position.to_string(),
+                "m.text": [{"body": option}],
+            }))
+            .collect();
+        // Clients without support for polls show the text
+        let fallback = options.iter()
+            .enumerate()
+            .fold(question.clone(), |text, (position, option)| {
+                format!("{}\n{}. {}", text, position + 1, option)
+            });
+        Ok(json!({
+            "m.poll": {
+                "kind": "m.disclosed",
+                "max_selections": 1,
+                "question": {"m.text": [{"body": question}]},
+                "answers": answers,
+            },
+            "m.text": [{"body": fallback}],
+        }))
+    }
+
+    fn max_send_time(&self) -> Duration{
+        Duration::from_secs(TIMEOUT)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use serde_json::json;
+    use crate::stub::Stub;
+    use super::*;
+
+    fn category(homeserver: &str, token: Option<&str>) -> Category{
+        serde_json::from_value(json!({
+            "id": 1,
+            "name": "Rust",
+            "chat_id": "",
+            "thread_id": 0,
+            "backend": "matrix",
+            "matrix_homeserver": homeserver,
+            "matrix_room_id": "!room:example.org",
+            "matrix_token": token,
+        })).unwrap()
+    }
+
+    fn message() -> Message{
+        Message{
+            title: "Tip".to_string(),
+            text: "Borrow".to_string(),
+            html: "<b>Tip</b>\n\nBorrow".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_the_tip_as_a_message(){
+        let stub = Stub::start(vec![(200, json!({"event_id": "$event"}))]);
+
+        let receipt = Matrix::new().send_text(&category(&stub.url, Some("secret")), &message()).await.unwrap();
+
+        assert_eq!(receipt.message_id.as_deref(), Some("$event"));
+        let requests = stub.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].path.starts_with(
+            "/_matrix/client/v3/rooms/!room:example.org/send/m.room.message/publirs-"),
+            "{}", requests[0].path);
+        assert_eq!(requests[0].authorization.as_deref(), Some("Bearer secret"));
+        assert_eq!(requests[0].body["body"], "Tip\n\nBorrow");
+        assert_eq!(requests[0].body["formatted_body"], "<b>Tip</b><br><br>Borrow");
+    }
+
+    #[tokio::test]
+    async fn returns_the_error_of_the_homeserver(){
+        let stub = Stub::start(vec![(403, json!({"errcode": "M_FORBIDDEN",
+            "error": "User not in room"}))]);
+
+        let result = Matrix::new().send_text(&category(&stub.url, Some("secret")), &message()).await;
+
+        match result{
+            Err(CustomError::OtherError(e)) => {
+                assert!(e.contains("M_FORBIDDEN"), "{}", e);
+                assert!(e.contains("User not in room"), "{}", e);
+            },
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[tokio::test]
+    async fn needs_the_room_and_the_token(){
+        let stub = Stub::start(vec![(200, json!({"event_id": "$event"}))]);
+
+        let result = Matrix::new().send_text(&category(&stub.url, None), &message()).await;
+
+        match result{
+            Err(CustomError::OtherError(e)) => assert!(e.contains("no Matrix room"), "{}", e),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert!(stub.requests().is_empty());
+    }
+}
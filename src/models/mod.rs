@@ -1,6 +1,39 @@
+use std::str::FromStr;
+use sqlx::{sqlite::SqliteRow, Row};
+
 pub mod answer;
+pub mod api_key;
+pub mod backup;
 pub mod category;
+pub mod discord;
+pub mod import;
+pub mod job;
+pub mod kind;
+pub mod listing;
+pub mod markup;
+pub mod mastodon;
+pub mod matrix;
 pub mod poll;
+pub mod publication;
+pub mod publish_at;
+pub mod publisher;
+pub mod rate_limit;
+pub mod rotation;
+pub mod schedule;
+pub mod search;
+pub mod strategy;
 pub mod telegram;
+pub mod template;
 pub mod tip;
+pub mod validation;
 pub mod error;
+
+/// Parses a column stored as text. A value that is not known is an error, so
+/// a corrupt row is never taken for something else.
+fn decode<T: FromStr>(row: &SqliteRow, column: &str) -> Result<T, sqlx::Error>{
+    let value: String = row.try_get(column)?;
+    value.parse().map_err(|_| sqlx::Error::ColumnDecode{
+        index: column.to_string(),
+        source: format!("unknown {} {}", column, value).into(),
+    })
+}
@@ -1,7 +1,14 @@
+use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
-use sqlx::{sqlite::{SqlitePool, SqliteRow}, query, Row};
+use sqlx::{sqlite::{Sqlite, SqlitePool, SqliteRow}, query, Executor, Row, Transaction};
 use super::{
-    answer::{Answer, NewBasicAnswer},
+    answer::{Answer, NewAnswer, NewBasicAnswer},
+    job,
+    kind::Kind,
+    listing::{Columns, ListParams},
+    publish_at::{self, DUE, DUE_FIRST},
+    strategy::Candidate,
+    validation::{Errors, Validate, MAX_QUESTION_LENGTH},
     error::CustomError
 };
 
@@ -11,13 +18,28 @@ pub struct Poll{
     category_id: i64,
     question: String,
     #[serde(default = "get_default_published")]
-    published: bool
+    published: bool,
+    /// Used by the priority and weighted strategies, the higher the sooner
+    #[serde(default)]
+    priority: i64,
+    /// Not published before this date, and before the rest once it comes
+    #[serde(default, deserialize_with = "publish_at::deserialize")]
+    publish_at: Option<DateTime<Utc>>,
+    /// Kept by the publications, what is sent in a change is ignored
+    #[serde(default)]
+    published_count: i64,
+    #[serde(default)]
+    last_published_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NewPoll{
     category_id: i64,
     question: String,
+    #[serde(default)]
+    priority: i64,
+    #[serde(default, deserialize_with = "publish_at::deserialize")]
+    publish_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -25,6 +47,10 @@ pub struct NewPollWithAnswers{
     pub category: String,
     pub question: String,
     pub answers: Vec<NewBasicAnswer>,
+    #[serde(default)]
+    pub priority: i64,
+    #[serde(default, deserialize_with = "publish_at::deserialize")]
+    pub publish_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -33,40 +59,212 @@ pub struct PollWithAnswers{
     category_id: i64,
     question: String,
     published: bool,
+    #[serde(default)]
+    priority: i64,
+    #[serde(default, deserialize_with = "publish_at::deserialize")]
+    publish_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    published_count: i64,
+    #[serde(default)]
+    last_published_at: Option<DateTime<Utc>>,
     pub answers: Vec<Answer>,
 }
 
+const COLUMNS: Columns = Columns{
+    table: "polls",
+    search: &["question"],
+    sort: &["id", "question", "category_id", "published", "priority", "publish_at",
+           "published_count", "last_published_at"],
+    categorized: true,
+};
+
 fn get_default_published() -> bool{
     false
 }
 
+impl Validate for NewPoll{
+    fn check(&self, errors: &mut Errors){
+        errors.text("question", &self.question, MAX_QUESTION_LENGTH);
+    }
+}
+
+impl Validate for Poll{
+    fn check(&self, errors: &mut Errors){
+        errors.text("question", &self.question, MAX_QUESTION_LENGTH);
+    }
+}
+
+/// Every poll is sent as a quiz, so it needs exactly one right answer
+impl Validate for NewPollWithAnswers{
+    fn check(&self, errors: &mut Errors){
+        errors.required("category", &self.category);
+        errors.text("question", &self.question, MAX_QUESTION_LENGTH);
+        errors.answers(self.answers.iter().map(|answer| (answer.text.as_str(), answer.isok)));
+    }
+}
+
+impl Validate for PollWithAnswers{
+    fn check(&self, errors: &mut Errors){
+        errors.text("question", &self.question, MAX_QUESTION_LENGTH);
+        errors.answers(self.answers.iter().map(|answer| (answer.get_text(), answer.get_isok())));
+    }
+}
+
 impl NewPoll{
     pub fn new(category_id: i64, question: String) -> Self{
         Self{
             category_id,
             question,
+            priority: 0,
+            publish_at: None,
         }
     }
+
+    pub fn set_priority(&mut self, priority: i64){
+        self.priority = priority;
+    }
+
+    pub fn set_publish_at(&mut self, publish_at: Option<DateTime<Utc>>){
+        self.publish_at = publish_at;
+    }
 }
+
+impl Candidate for Poll{
+    fn get_id(&self) -> i64{
+        self.id
+    }
+
+    fn get_priority(&self) -> i64{
+        self.priority
+    }
+
+    fn get_publish_at(&self) -> Option<DateTime<Utc>>{
+        self.publish_at
+    }
+}
+
 impl PollWithAnswers{
-    pub fn new(id: i64, category_id: i64, question: String, published: bool, answers: Vec<Answer>) -> Self{
+    pub fn new(poll: Poll, answers: Vec<Answer>) -> Self{
+        Self{
+            id: poll.id,
+            category_id: poll.category_id,
+            question: poll.question,
+            published: poll.published,
+            priority: poll.priority,
+            publish_at: poll.publish_at,
+            published_count: poll.published_count,
+            last_published_at: poll.last_published_at,
+            answers,
+        }
+    }
+
+    pub fn get_id(&self) -> i64{
+        self.id
+    }
+
+    pub fn get_question(&self) -> &str{
+        &self.question
+    }
+
+    /// Splits the poll from its answers
+    pub fn into_parts(self) -> (Poll, Vec<NewBasicAnswer>){
+        let poll = Poll{
+            id: self.id,
+            category_id: self.category_id,
+            question: self.question,
+            published: self.published,
+            priority: self.priority,
+            publish_at: self.publish_at,
+            published_count: self.published_count,
+            last_published_at: self.last_published_at,
+        };
+        let answers = self.answers.into_iter().map(NewBasicAnswer::from).collect();
+        (poll, answers)
+    }
+
+    /// Creates the poll and its answers in a single transaction, so there
+    /// are all of them or none
+    pub async fn create(pool: &SqlitePool, new_poll: NewPoll, answers: Vec<NewBasicAnswer>)
+            -> Result<PollWithAnswers, CustomError>{
+        let mut tx = pool.begin()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        let poll = Poll::create(&mut tx, new_poll).await?;
+        let created = create_answers(&mut tx, poll.id, answers).await?;
+        tx.commit()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        Ok(Self::new(poll, created))
+    }
+
+    /// Gets the poll with its answers in order
+    pub async fn read(pool: &SqlitePool, id: i64) -> Result<Option<PollWithAnswers>, CustomError>{
+        match Poll::read(pool, id).await?{
+            Some(poll) => {
+                let answers = Answer::read_for_poll(pool, id).await?;
+                Ok(Some(Self::new(poll, answers)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces the poll and all its answers in a single transaction. The
+    /// answers are created again in the given order.
+    pub async fn update(pool: &SqlitePool, poll: Poll, answers: Vec<NewBasicAnswer>)
+            -> Result<PollWithAnswers, CustomError>{
+        // The pool has a single connection, so it has to be read before the
+        // transaction starts
+        if Poll::read(pool, poll.id).await?.is_none(){
+            return Err(CustomError::NotFound);
+        }
+        let mut tx = pool.begin()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        let poll = Poll::update(&mut tx, poll).await?;
+        Answer::delete_for_poll(&mut tx, poll.id).await?;
+        let created = create_answers(&mut tx, poll.id, answers).await?;
+        tx.commit()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        Ok(Self::new(poll, created))
+    }
+}
+
+async fn create_answers(tx: &mut Transaction<'_, Sqlite>, poll_id: i64, answers: Vec<NewBasicAnswer>)
+        -> Result<Vec<Answer>, CustomError>{
+    let mut created = Vec::new();
+    for answer in answers{
+        let new_answer = NewAnswer::new(poll_id, answer.text, answer.isok);
+        created.push(Answer::create(&mut *tx, new_answer).await?);
+    }
+    Ok(created)
+}
+
+impl Poll{
+    pub fn new(id: i64, category_id: i64, question: String, published: bool, priority: i64,
+            publish_at: Option<DateTime<Utc>>) -> Self{
         Self{
             id,
             category_id,
             question,
             published,
-            answers,
+            priority,
+            publish_at,
+            published_count: 0,
+            last_published_at: None,
         }
     }
-}
 
-impl Poll{
     fn from_row(row: SqliteRow) -> Self{
         Self{
             id: row.get("id"),
             category_id: row.get("category_id"),
             question: row.get("question"),
-            published: row.get("published")
+            published: row.get("published"),
+            priority: row.get("priority"),
+            publish_at: row.get("publish_at"),
+            published_count: row.get("published_count"),
+            last_published_at: row.get("last_published_at"),
         }
     }
 
@@ -78,6 +276,14 @@ impl Poll{
         self.category_id
     }
 
+    pub fn set_id(&mut self, id: i64){
+        self.id = id;
+    }
+
+    pub fn set_category_id(&mut self, category_id: i64){
+        self.category_id = category_id;
+    }
+
     pub fn get_question(&self) -> &str{
         &self.question
     }
@@ -86,20 +292,26 @@ impl Poll{
         self.published
     }
 
-    pub fn set_published(&mut self, published: bool){
-        self.published = published;
+    pub fn get_priority(&self) -> i64{
+        self.priority
+    }
+
+    pub fn get_publish_at(&self) -> Option<DateTime<Utc>>{
+        self.publish_at
     }
 
-    pub async fn create(pool: &SqlitePool, new_poll: NewPoll)
+    pub async fn create<'e, E: Executor<'e, Database = Sqlite>>(executor: E, new_poll: NewPoll)
             -> Result<Poll, CustomError>{
         tracing::info!("Data: {:?}", new_poll);
-        let sql = "INSERT INTO polls (category_id, question
-                   VALUES ($1, $2) RETURNING *;";
+        let sql = "INSERT INTO polls (category_id, question, priority, publish_at)
+                   VALUES ($1, $2, $3, $4) RETURNING *;";
         query(sql)
             .bind(new_poll.category_id)
             .bind(new_poll.question)
+            .bind(new_poll.priority)
+            .bind(new_poll.publish_at)
             .map(Self::from_row)
-            .fetch_one(pool)
+            .fetch_one(executor)
             .await
             .map_err(|e| {
                 CustomError::ServerError(e.to_string())
@@ -117,9 +329,50 @@ impl Poll{
             })
     }
 
+    /// The next poll that can be published, the scheduled ones that are due
+    /// before the rest
     pub async fn read_not_published(pool: &SqlitePool) -> Result<Option<Poll>, CustomError>{
-        let sql = "SELECT * FROM polls WHERE published = FALSE ORDER BY id LIMIT 1";
-        query(sql)
+        let sql = format!("SELECT * FROM polls WHERE published = FALSE AND {} AND {}
+                           ORDER BY {}, id LIMIT 1", DUE, job::without_open_job(Kind::Poll, "polls"),
+                           DUE_FIRST);
+        query(&sql)
+            .map(Self::from_row)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// Every poll of the category not published yet that is due, the
+    /// strategy of the category chooses which one goes next
+    pub async fn read_not_published_in_category(pool: &SqlitePool, category_id: i64) -> Result<Vec<Poll>, CustomError>{
+        let sql = format!("SELECT * FROM polls WHERE published = FALSE AND category_id = $1
+                           AND {} AND {} ORDER BY id", DUE, job::without_open_job(Kind::Poll, "polls"));
+        query(&sql)
+            .bind(category_id)
+            .map(Self::from_row)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// The poll published the longest ago, or never, of a category that
+    /// recycles, once its cooldown has passed
+    pub async fn read_recyclable(pool: &SqlitePool, category_id: Option<i64>) -> Result<Option<Poll>, CustomError>{
+        let sql = format!("SELECT polls.* FROM polls JOIN categories ON categories.id = polls.category_id
+                   WHERE polls.published = TRUE AND categories.recycle = TRUE AND {}
+                   AND ($1 IS NULL OR polls.category_id = $1)
+                   AND (polls.last_published_at IS NULL
+                        OR datetime(polls.last_published_at) <=
+                           datetime('now', '-' || categories.cooldown || ' days'))
+                   ORDER BY polls.last_published_at IS NOT NULL,
+                            datetime(polls.last_published_at), polls.id
+                   LIMIT 1", job::without_open_job(Kind::Poll, "polls"));
+        query(&sql)
+            .bind(category_id)
             .map(Self::from_row)
             .fetch_optional(pool)
             .await
@@ -128,6 +381,37 @@ impl Poll{
             })
     }
 
+    /// Marks the poll as published now, once more
+    pub async fn mark_published<'e, E: Executor<'e, Database = Sqlite>>(executor: E, id: i64)
+            -> Result<Poll, CustomError>{
+        let sql = "UPDATE polls SET published = TRUE, published_count = published_count + 1,
+                   last_published_at = $2 WHERE id = $1 RETURNING *;";
+        query(sql)
+            .bind(id)
+            .bind(Utc::now())
+            .map(Self::from_row)
+            .fetch_one(executor)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// The polls of the category scheduled for a date that hasn't come yet,
+    /// the earliest first
+    pub async fn read_scheduled_in_category(pool: &SqlitePool, category_id: i64) -> Result<Vec<Poll>, CustomError>{
+        let sql = format!("SELECT * FROM polls WHERE published = FALSE AND category_id = $1
+                           AND NOT {} ORDER BY datetime(publish_at), id", DUE);
+        query(&sql)
+            .bind(category_id)
+            .map(Self::from_row)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
     pub async fn read_all(pool: &SqlitePool) -> Result<Vec<Poll>, CustomError>{
         let sql = "SELECT * FROM polls";
         query(sql)
@@ -139,16 +423,23 @@ impl Poll{
             })
     }
 
-    pub async fn update(pool: &SqlitePool, poll: Poll) -> Result<Poll, CustomError>{
+    /// Reads a page of the polls and how many there are with the filters
+    pub async fn read_page(pool: &SqlitePool, params: &ListParams) -> Result<(Vec<Poll>, i64), CustomError>{
+        params.read(pool, &COLUMNS, |row| Ok(Self::from_row(row))).await
+    }
+
+    pub async fn update<'e, E: Executor<'e, Database = Sqlite>>(executor: E, poll: Poll) -> Result<Poll, CustomError>{
         let sql = "UPDATE polls SET category_id = $2, question = $3,
-                    published = $4 WHERE id = $1 RETURNING * ;";
+                    published = $4, priority = $5, publish_at = $6 WHERE id = $1 RETURNING * ;";
         query(sql)
             .bind(poll.id)
             .bind(poll.category_id)
             .bind(poll.question)
             .bind(poll.published)
+            .bind(poll.priority)
+            .bind(poll.publish_at)
             .map(Self::from_row)
-            .fetch_one(pool)
+            .fetch_one(executor)
             .await
             .map_err(|e| {
                 tracing::error!("Error: {}", e);
@@ -156,15 +447,171 @@ impl Poll{
             })
     }
 
+    /// Creates a copy of the poll of a backup, with a new id but with what
+    /// the publications have kept
+    pub async fn restore<'e, E: Executor<'e, Database = Sqlite>>(executor: E, poll: Poll) -> Result<Poll, CustomError>{
+        let sql = "INSERT INTO polls (category_id, question, published, priority, publish_at,
+                   published_count, last_published_at)
+                   VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *;";
+        query(sql)
+            .bind(poll.category_id)
+            .bind(poll.question)
+            .bind(poll.published)
+            .bind(poll.priority)
+            .bind(poll.publish_at)
+            .bind(poll.published_count)
+            .bind(poll.last_published_at)
+            .map(Self::from_row)
+            .fetch_one(executor)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// Overwrites the poll with the one of a backup, what the publications
+    /// have kept too
+    pub async fn replace<'e, E: Executor<'e, Database = Sqlite>>(executor: E, poll: Poll) -> Result<Poll, CustomError>{
+        let sql = "UPDATE polls SET category_id = $2, question = $3, published = $4,
+                   priority = $5, publish_at = $6, published_count = $7,
+                   last_published_at = $8 WHERE id = $1 RETURNING * ;";
+        query(sql)
+            .bind(poll.id)
+            .bind(poll.category_id)
+            .bind(poll.question)
+            .bind(poll.published)
+            .bind(poll.priority)
+            .bind(poll.publish_at)
+            .bind(poll.published_count)
+            .bind(poll.last_published_at)
+            .map(Self::from_row)
+            .fetch_one(executor)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error: {}", e);
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// Deletes the poll together with its answers
     pub async fn delete(pool: &SqlitePool, id: i64) -> Result<Poll, CustomError>{
+        let mut tx = pool.begin()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        Answer::delete_for_poll(&mut tx, id).await?;
         let sql = "DELETE from polls WHERE id = $1 RETURNING * ;";
-        query(sql)
+        let poll = query(sql)
             .bind(id)
             .map(Self::from_row)
-            .fetch_one(pool)
+            .fetch_optional(&mut tx)
             .await
             .map_err(|e| {
                 CustomError::ServerError(e.to_string())
+            })?
+            .ok_or(CustomError::NotFound)?;
+        tx.commit()
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))?;
+        Ok(poll)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::database;
+    use super::*;
+
+    fn answers(texts: &[&str]) -> Vec<NewBasicAnswer>{
+        texts.iter()
+            .enumerate()
+            .map(|(index, text)| NewBasicAnswer{
+                text: text.to_string(),
+                isok: index == 0,
             })
+            .collect()
+    }
+
+    fn texts(answers: &[Answer]) -> Vec<&str>{
+        answers.iter().map(|answer| answer.get_text()).collect()
+    }
+
+    /// Makes the insert of the answer with the given text fail
+    async fn fail_on_answer(pool: &SqlitePool, text: &str){
+        query(&format!("CREATE TRIGGER fail_answer BEFORE INSERT ON answers
+                        WHEN new.text = '{}' BEGIN SELECT RAISE(ABORT, 'failed'); END;", text))
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn creates_the_poll_with_its_answers(){
+        let pool = database::memory().await;
+        let new_poll = NewPoll::new(1, "Which one moves?".to_string());
+
+        let created = PollWithAnswers::create(&pool, new_poll, answers(&["String", "i32"])).await.unwrap();
+
+        let read = PollWithAnswers::read(&pool, created.get_id()).await.unwrap().unwrap();
+        assert_eq!(read.get_question(), "Which one moves?");
+        assert_eq!(texts(&read.answers), vec!["String", "i32"]);
+    }
+
+    #[tokio::test]
+    async fn replaces_the_poll_and_its_answers(){
+        let pool = database::memory().await;
+        let new_poll = NewPoll::new(1, "Which one moves?".to_string());
+        let created = PollWithAnswers::create(&pool, new_poll, answers(&["String", "i32"])).await.unwrap();
+        let (mut poll, _) = created.into_parts();
+        poll.question = "Which one copies?".to_string();
+
+        let updated = PollWithAnswers::update(&pool, poll, answers(&["u8", "Vec", "Box"])).await.unwrap();
+
+        assert_eq!(updated.get_question(), "Which one copies?");
+        let stored = Answer::read_for_poll(&pool, updated.get_id()).await.unwrap();
+        assert_eq!(texts(&stored), vec!["u8", "Vec", "Box"]);
+        assert_eq!(Answer::read_all(&pool).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn deletes_the_poll_with_its_answers(){
+        let pool = database::memory().await;
+        let new_poll = NewPoll::new(1, "Which one moves?".to_string());
+        let created = PollWithAnswers::create(&pool, new_poll, answers(&["String", "i32"])).await.unwrap();
+
+        Poll::delete(&pool, created.get_id()).await.unwrap();
+
+        assert!(Poll::read(&pool, created.get_id()).await.unwrap().is_none());
+        assert!(Answer::read_all(&pool).await.unwrap().is_empty());
+        assert!(matches!(Poll::delete(&pool, created.get_id()).await, Err(CustomError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn leaves_no_poll_when_an_answer_fails(){
+        let pool = database::memory().await;
+        fail_on_answer(&pool, "i32").await;
+        let new_poll = NewPoll::new(1, "Which one moves?".to_string());
+
+        let result = PollWithAnswers::create(&pool, new_poll, answers(&["String", "i32"])).await;
+
+        assert!(matches!(result, Err(CustomError::ServerError(_))), "{:?}", result);
+        assert!(Poll::read_all(&pool).await.unwrap().is_empty());
+        assert!(Answer::read_all(&pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_the_poll_as_it_was_when_an_answer_fails(){
+        let pool = database::memory().await;
+        let new_poll = NewPoll::new(1, "Which one moves?".to_string());
+        let created = PollWithAnswers::create(&pool, new_poll, answers(&["String", "i32"])).await.unwrap();
+        fail_on_answer(&pool, "Box").await;
+        let (mut poll, _) = created.into_parts();
+        poll.question = "Which one copies?".to_string();
+
+        let result = PollWithAnswers::update(&pool, poll, answers(&["u8", "Box"])).await;
+
+        assert!(result.is_err());
+        let read = PollWithAnswers::read(&pool, 1).await.unwrap().unwrap();
+        assert_eq!(read.get_question(), "Which one moves?");
+        assert_eq!(texts(&read.answers), vec!["String", "i32"]);
     }
 }
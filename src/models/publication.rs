@@ -0,0 +1,171 @@
+use std::str::FromStr;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use sqlx::{sqlite::{Sqlite, SqlitePool, SqliteRow}, query, QueryBuilder, Row};
+use super::{
+    decode,
+    category::Category,
+    kind::Kind,
+    publisher::{Backend, Receipt},
+    error::CustomError,
+};
+
+/// Result of trying to publish an item
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome{
+    Sent,
+    Failed,
+}
+
+/// Every time a tip or a poll is sent, or fails to be sent
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Publication{
+    id: i64,
+    kind: Kind,
+    item_id: i64,
+    category_id: i64,
+    backend: Backend,
+    chat_id: String,
+    thread_id: i64,
+    message_id: Option<String>,
+    published_at: DateTime<Utc>,
+    outcome: Outcome,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PublicationFilter{
+    pub category_id: Option<i64>,
+    pub kind: Option<Kind>,
+    pub outcome: Option<Outcome>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl Outcome{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            Self::Sent => "sent",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for Outcome{
+    type Err = CustomError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s{
+            "sent" => Ok(Self::Sent),
+            "failed" => Ok(Self::Failed),
+            _ => Err(CustomError::BadRequest),
+        }
+    }
+}
+
+impl Publication{
+    /// Reads a publication, a kind, a backend or an outcome that is not
+    /// known is an error, the history would show what didn't happen
+    fn from_row(row: SqliteRow) -> Result<Self, sqlx::Error>{
+        Ok(Self{
+            id: row.get("id"),
+            kind: decode(&row, "kind")?,
+            item_id: row.get("item_id"),
+            category_id: row.get("category_id"),
+            backend: decode(&row, "backend")?,
+            chat_id: row.get("chat_id"),
+            thread_id: row.get("thread_id"),
+            message_id: row.get("message_id"),
+            published_at: row.get("published_at"),
+            outcome: decode(&row, "outcome")?,
+            error: row.get("error"),
+        })
+    }
+
+    /// Records the result of publishing an item of a category
+    pub async fn create(pool: &SqlitePool, kind: Kind, item_id: i64, category: &Category,
+            result: &Result<Receipt, CustomError>) -> Result<Publication, CustomError>{
+        let (outcome, message_id, error) = match result{
+            Ok(receipt) => (Outcome::Sent, receipt.message_id.clone(), None),
+            Err(e) => (Outcome::Failed, None, Some(e.to_string())),
+        };
+        let sql = "INSERT INTO publications (kind, item_id, category_id, backend,
+                   chat_id, thread_id, message_id, published_at, outcome, error)
+                   VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING *;";
+        query(sql)
+            .bind(kind.as_str())
+            .bind(item_id)
+            .bind(category.get_id())
+            .bind(category.get_backend().as_str())
+            .bind(category.get_chat_id())
+            .bind(category.get_thread_id())
+            .bind(message_id)
+            .bind(Utc::now())
+            .bind(outcome.as_str())
+            .bind(error)
+            .try_map(Self::from_row)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    pub async fn search(pool: &SqlitePool, filter: &PublicationFilter) -> Result<Vec<Publication>, CustomError>{
+        let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "SELECT * FROM publications WHERE 1 = 1");
+        if let Some(category_id) = filter.category_id {
+            builder.push(" AND category_id = ").push_bind(category_id);
+        }
+        if let Some(kind) = filter.kind {
+            builder.push(" AND kind = ").push_bind(kind.as_str());
+        }
+        if let Some(outcome) = filter.outcome {
+            builder.push(" AND outcome = ").push_bind(outcome.as_str());
+        }
+        if let Some(from) = filter.from {
+            builder.push(" AND published_at >= ").push_bind(from);
+        }
+        if let Some(to) = filter.to {
+            builder.push(" AND published_at <= ").push_bind(to);
+        }
+        builder.push(" ORDER BY published_at DESC, id DESC");
+        builder.build()
+            .try_map(Self::from_row)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::database;
+    use super::*;
+
+    #[tokio::test]
+    async fn an_unknown_value_is_an_error(){
+        let pool = database::memory().await;
+        for (kind, backend, outcome) in [("video", "telegram", "sent"), ("tip", "irc", "sent"),
+                ("tip", "telegram", "lost")]{
+            query("DELETE FROM publications").execute(&pool).await.unwrap();
+            query("INSERT INTO publications (kind, item_id, category_id, backend, chat_id,
+                   thread_id, published_at, outcome) VALUES ($1, 1, 1, $2, '@rust', 0, $3, $4)")
+                .bind(kind)
+                .bind(backend)
+                .bind(Utc::now())
+                .bind(outcome)
+                .execute(&pool)
+                .await
+                .unwrap();
+
+            match Publication::search(&pool, &PublicationFilter::default()).await{
+                Err(CustomError::ServerError(e)) => assert!(e.contains("unknown"), "{}", e),
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+    }
+}
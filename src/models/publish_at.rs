@@ -0,0 +1,30 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde::{de, Deserialize, Deserializer};
+
+/// Condition of the items that can be published now, the ones without a
+/// date and the ones whose date has come. `datetime` makes the comparison
+/// independent of how the date was written.
+pub const DUE: &str = "(publish_at IS NULL OR datetime(publish_at) <= CURRENT_TIMESTAMP)";
+
+/// Order of the items that can be published now, the scheduled ones first
+/// and the earliest of them before the rest
+pub const DUE_FIRST: &str = "publish_at IS NULL, datetime(publish_at)";
+
+/// Reads the date an item is scheduled for. Besides RFC 3339, it takes the
+/// value of a `datetime-local` input, in UTC, and an empty value clears it.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>{
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    match value.as_deref().map(str::trim){
+        None | Some("") => Ok(None),
+        Some(value) => parse(value).map(Some).map_err(de::Error::custom),
+    }
+}
+
+fn parse(value: &str) -> Result<DateTime<Utc>, String>{
+    DateTime::parse_from_rfc3339(value)
+        .map(|datetime| datetime.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+            .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M"))
+            .map(|naive| Utc.from_utc_datetime(&naive)))
+        .map_err(|_| format!("{} is not a date like 2023-06-28T10:00:00Z", value))
+}
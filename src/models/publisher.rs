@@ -0,0 +1,198 @@
+use std::{collections::HashMap, fmt, str::FromStr, sync::Arc, time::Duration};
+#[cfg(test)]
+use std::sync::Mutex;
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+#[cfg(test)]
+use serde_json::json;
+
+use super::{
+    category::Category,
+    discord::Discord,
+    mastodon::Mastodon,
+    matrix::Matrix,
+    telegram::Telegram,
+    error::CustomError,
+};
+
+/// Platform where the content of a category is published
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend{
+    Telegram,
+    Mastodon,
+    Matrix,
+    Discord,
+}
+
+/// A text message. Besides the HTML rendered version, the raw parts are
+/// kept for the backends that build their own layout.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Message{
+    pub title: String,
+    pub text: String,
+    pub html: String,
+}
+
+/// A poll with only one right answer
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Quiz{
+    pub question: String,
+    pub options: Vec<String>,
+    pub correct_option_id: usize,
+}
+
+/// What the backend returns when the content has been published
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Receipt{
+    pub message_id: Option<String>,
+}
+
+#[async_trait]
+pub trait Publisher: Send + Sync{
+    async fn send_text(&self, category: &Category, message: &Message) -> Result<Receipt, CustomError>;
+    async fn send_quiz(&self, category: &Category, quiz: &Quiz) -> Result<Receipt, CustomError>;
+    /// What would be sent for the message, without sending it
+    fn preview_text(&self, category: &Category, message: &Message) -> Result<Value, CustomError>;
+    /// What would be sent for the quiz, without sending it
+    fn preview_quiz(&self, category: &Category, quiz: &Quiz) -> Result<Value, CustomError>;
+    /// Longest a message can take to be sent, counting the retries
+    fn max_send_time(&self) -> Duration;
+}
+
+/// The publishers available, one for each backend
+#[derive(Clone, Default)]
+pub struct Publishers{
+    publishers: HashMap<Backend, Arc<dyn Publisher>>,
+}
+
+/// What has been sent through a `RecordingPublisher`
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sent{
+    Text{category_id: i64, message: Message},
+    Quiz{category_id: i64, quiz: Quiz},
+}
+
+/// Publisher that doesn't send anything, it only keeps what it is asked to
+/// send, so it can be checked later. Meant to replace the real publishers
+/// when testing.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct RecordingPublisher{
+    sent: Mutex<Vec<Sent>>,
+}
+
+impl Backend{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            Self::Telegram => "telegram",
+            Self::Mastodon => "mastodon",
+            Self::Matrix => "matrix",
+            Self::Discord => "discord",
+        }
+    }
+}
+
+impl fmt::Display for Backend{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Backend{
+    type Err = CustomError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s{
+            "telegram" => Ok(Self::Telegram),
+            "mastodon" => Ok(Self::Mastodon),
+            "matrix" => Ok(Self::Matrix),
+            "discord" => Ok(Self::Discord),
+            _ => Err(CustomError::BadRequest),
+        }
+    }
+}
+
+impl Publishers{
+    pub fn new(telegram: Telegram) -> Self{
+        Self::default()
+            .with(Backend::Telegram, Arc::new(telegram))
+            .with(Backend::Mastodon, Arc::new(Mastodon::new()))
+            .with(Backend::Matrix, Arc::new(Matrix::new()))
+            .with(Backend::Discord, Arc::new(Discord::new()))
+    }
+
+    /// Sets the publisher for a backend, replacing the previous one
+    pub fn with(mut self, backend: Backend, publisher: Arc<dyn Publisher>) -> Self{
+        self.publishers.insert(backend, publisher);
+        self
+    }
+
+    pub fn get(&self, backend: Backend) -> Result<Arc<dyn Publisher>, CustomError>{
+        self.publishers
+            .get(&backend)
+            .cloned()
+            .ok_or_else(|| CustomError::OtherError(
+                format!("There is no publisher for {}", backend)))
+    }
+
+    /// Longest any of the publishers can take to send a message
+    pub fn max_send_time(&self) -> Duration{
+        self.publishers
+            .values()
+            .map(|publisher| publisher.max_send_time())
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+impl RecordingPublisher{
+    pub fn new() -> Self{
+        Self::default()
+    }
+
+    pub fn sent(&self) -> Vec<Sent>{
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Publisher for RecordingPublisher{
+    async fn send_text(&self, category: &Category, message: &Message) -> Result<Receipt, CustomError>{
+        let mut sent = self.sent.lock().unwrap();
+        sent.push(Sent::Text{
+            category_id: category.get_id(),
+            message: message.clone(),
+        });
+        Ok(Receipt{
+            message_id: Some(sent.len().to_string()),
+        })
+    }
+
+    async fn send_quiz(&self, category: &Category, quiz: &Quiz) -> Result<Receipt, CustomError>{
+        let mut sent = self.sent.lock().unwrap();
+        sent.push(Sent::Quiz{
+            category_id: category.get_id(),
+            quiz: quiz.clone(),
+        });
+        Ok(Receipt{
+            message_id: Some(sent.len().to_string()),
+        })
+    }
+
+    fn preview_text(&self, _category: &Category, message: &Message) -> Result<Value, CustomError>{
+        Ok(json!(message))
+    }
+
+    fn preview_quiz(&self, _category: &Category, quiz: &Quiz) -> Result<Value, CustomError>{
+        Ok(json!(quiz))
+    }
+
+    fn max_send_time(&self) -> Duration{
+        Duration::ZERO
+    }
+}
@@ -0,0 +1,190 @@
+use std::{collections::HashMap, sync::Mutex, time::{Duration, Instant}};
+use tracing::debug;
+
+/// Messages allowed by Telegram to a bot
+#[derive(Debug, Clone, Copy)]
+pub struct Limits{
+    /// Messages per second to all the chats together
+    pub global: u32,
+    /// Messages per minute to a single chat
+    pub chat: u32,
+}
+
+/// The limits of a bot in groups, as told in the FAQ of the Bot API
+pub const DEFAULT_LIMITS: Limits = Limits{
+    global: 30,
+    chat: 20,
+};
+
+/// Bucket that refills at `rate` tokens per second up to its capacity, each
+/// message takes one. When it is empty the tokens are taken on credit, so
+/// every message waits for its own turn.
+#[derive(Debug)]
+struct TokenBucket{
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket{
+    fn new(capacity: f64, rate: f64, now: Instant) -> Self{
+        Self{
+            capacity,
+            rate,
+            tokens: capacity,
+            updated: now,
+        }
+    }
+
+    /// Tokens there would be at `now`, without the limit of the capacity
+    fn refilled(&self, now: Instant) -> f64{
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * self.rate
+    }
+
+    /// Whether it is as it was when it was created
+    fn is_full(&self, now: Instant) -> bool{
+        self.refilled(now) >= self.capacity
+    }
+
+    /// Takes a token and returns how long to wait until it is really there
+    fn take(&mut self, now: Instant) -> Duration{
+        self.tokens = self.refilled(now).min(self.capacity);
+        self.updated = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        }else{
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Buckets{
+    global: TokenBucket,
+    chats: HashMap<String, TokenBucket>,
+}
+
+/// Spaces the messages so they keep within the global limit and the limit
+/// of each chat. The whole global limit can be used in a burst, a chat gets
+/// a message at a time.
+#[derive(Debug)]
+pub struct RateLimiter{
+    limits: Limits,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter{
+    pub fn new(limits: Limits) -> Self{
+        let global = limits.global as f64;
+        Self{
+            limits,
+            buckets: Mutex::new(Buckets{
+                global: TokenBucket::new(global, global, Instant::now()),
+                chats: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Takes the turn of a message to the chat and returns how long to wait
+    /// for it. The buckets of the chats that are full again are dropped, they
+    /// are the same as new ones, so they don't pile up.
+    fn take(&self, chat_id: &str, now: Instant) -> Duration{
+        let mut buckets = self.buckets.lock().unwrap();
+        let chat_rate = self.limits.chat as f64 / 60.0;
+        buckets.chats.retain(|_, bucket| !bucket.is_full(now));
+        let global = buckets.global.take(now);
+        let chat = buckets.chats.entry(chat_id.to_string())
+            .or_insert_with(|| TokenBucket::new(1.0, chat_rate, now))
+            .take(now);
+        global.max(chat)
+    }
+
+    /// Waits for the turn of a message to the chat
+    pub async fn wait(&self, chat_id: &str){
+        let delay = self.take(chat_id, Instant::now());
+        if !delay.is_zero() {
+            debug!("Waiting {:?} to send to {}", delay, chat_id);
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn seconds(seconds: f64) -> Duration{
+        Duration::from_secs_f64(seconds)
+    }
+
+    #[test]
+    fn bucket_allows_a_burst_up_to_its_capacity(){
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(3.0, 1.0, now);
+        assert_eq!(bucket.take(now), Duration::ZERO);
+        assert_eq!(bucket.take(now), Duration::ZERO);
+        assert_eq!(bucket.take(now), Duration::ZERO);
+        assert_eq!(bucket.take(now), seconds(1.0));
+    }
+
+    #[test]
+    fn bucket_gives_each_message_its_own_turn_when_empty(){
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(1.0, 2.0, now);
+        assert_eq!(bucket.take(now), Duration::ZERO);
+        assert_eq!(bucket.take(now), seconds(0.5));
+        assert_eq!(bucket.take(now), seconds(1.0));
+        // The first of the waiting messages has been sent
+        assert_eq!(bucket.take(now + seconds(0.5)), seconds(1.0));
+    }
+
+    #[test]
+    fn bucket_refills_up_to_its_capacity(){
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, 1.0, now);
+        bucket.take(now);
+        bucket.take(now);
+        assert!(!bucket.is_full(now + seconds(1.0)));
+        assert!(bucket.is_full(now + seconds(2.0)));
+        let later = now + seconds(60.0);
+        assert_eq!(bucket.take(later), Duration::ZERO);
+        assert_eq!(bucket.take(later), Duration::ZERO);
+        assert_eq!(bucket.take(later), seconds(1.0));
+    }
+
+    #[test]
+    fn limiter_spaces_the_messages_to_a_chat(){
+        let limiter = RateLimiter::new(Limits{global: 30, chat: 20});
+        let now = Instant::now();
+        assert_eq!(limiter.take("a", now), Duration::ZERO);
+        assert_eq!(limiter.take("b", now), Duration::ZERO);
+        assert_eq!(limiter.take("a", now), seconds(3.0));
+    }
+
+    #[test]
+    fn limiter_keeps_the_global_limit(){
+        let limiter = RateLimiter::new(Limits{global: 2, chat: 20});
+        let now = Instant::now();
+        assert_eq!(limiter.take("a", now), Duration::ZERO);
+        assert_eq!(limiter.take("b", now), Duration::ZERO);
+        assert_eq!(limiter.take("c", now), seconds(0.5));
+    }
+
+    #[test]
+    fn limiter_drops_the_idle_chats(){
+        let limiter = RateLimiter::new(Limits{global: 30, chat: 20});
+        let now = Instant::now();
+        limiter.take("a", now);
+        limiter.take("b", now + seconds(2.0));
+        assert_eq!(limiter.buckets.lock().unwrap().chats.len(), 2);
+        // A message every three seconds, the bucket of a is full again
+        limiter.take("c", now + seconds(3.0));
+        let buckets = limiter.buckets.lock().unwrap();
+        let mut chats: Vec<&String> = buckets.chats.keys().collect();
+        chats.sort();
+        assert_eq!(chats, vec!["b", "c"]);
+    }
+}
@@ -0,0 +1,56 @@
+use sqlx::{sqlite::{Sqlite, SqlitePool, SqliteRow}, query, Executor, Row};
+use super::{
+    job,
+    kind::Kind,
+    publish_at::{DUE, DUE_FIRST},
+    error::CustomError,
+};
+
+/// Takes turns between the categories with content to publish, so a category
+/// with a long queue doesn't hold back the rest
+pub struct Rotation;
+
+impl Rotation{
+    fn table(kind: Kind) -> &'static str{
+        match kind{
+            Kind::Tip => "tips",
+            Kind::Poll => "polls",
+        }
+    }
+
+    /// Chooses the category with content not published after the last one
+    /// published, or the first one when the last category is reached. A
+    /// category with scheduled content that is due goes before its turn.
+    pub async fn next(pool: &SqlitePool, kind: Kind) -> Result<Option<i64>, CustomError>{
+        let table = Self::table(kind);
+        let sql = format!("SELECT category_id FROM {} WHERE published = FALSE AND {} AND {}
+                           ORDER BY {}, category_id <= COALESCE(
+                               (SELECT category_id FROM rotations WHERE kind = $1), -1),
+                               category_id
+                           LIMIT 1", table, DUE, job::without_open_job(kind, table), DUE_FIRST);
+        query(&sql)
+            .bind(kind.as_str())
+            .map(|row: SqliteRow| row.get(0))
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| CustomError::ServerError(e.to_string()))
+    }
+
+    /// Remembers the category just published, the next turn is for the one
+    /// after it. It is done when the job is finished, so a send that fails
+    /// keeps the turn of the category.
+    pub async fn advance<'e, E: Executor<'e, Database = Sqlite>>(executor: E, kind: Kind, category_id: i64)
+            -> Result<(), CustomError>{
+        let sql = "INSERT INTO rotations (kind, category_id, updated_at)
+                   VALUES ($1, $2, CURRENT_TIMESTAMP)
+                   ON CONFLICT(kind) DO UPDATE SET
+                   category_id = excluded.category_id, updated_at = excluded.updated_at";
+        query(sql)
+            .bind(kind.as_str())
+            .bind(category_id)
+            .execute(executor)
+            .await
+            .map(|_| ())
+            .map_err(|e| CustomError::ServerError(e.to_string()))
+    }
+}
@@ -0,0 +1,386 @@
+use std::str::FromStr;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+use sqlx::{sqlite::{SqlitePool, SqliteRow}, query, Row};
+use super::{
+    decode,
+    kind::Kind,
+    validation::{Errors, Validate},
+    error::CustomError,
+};
+
+/// What to do with the runs that were missed while the server was down
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CatchUp{
+    /// Forget missed runs, only run when the schedule is on time
+    Skip,
+    /// Run once, no matter how many runs were missed
+    Once,
+    /// Run as many times as runs were missed, up to `max_catch_up`
+    All,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Schedule{
+    id: i64,
+    category_id: i64,
+    kind: Kind,
+    cron: String,
+    #[serde(default = "get_default_jitter")]
+    jitter: i64,
+    #[serde(default = "get_default_catch_up")]
+    catch_up: CatchUp,
+    #[serde(default = "get_default_max_catch_up")]
+    max_catch_up: i64,
+    #[serde(default = "get_default_active")]
+    active: bool,
+    last_run: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewSchedule{
+    category_id: i64,
+    kind: Kind,
+    cron: String,
+    #[serde(default = "get_default_jitter")]
+    jitter: i64,
+    #[serde(default = "get_default_catch_up")]
+    catch_up: CatchUp,
+    #[serde(default = "get_default_max_catch_up")]
+    max_catch_up: i64,
+    #[serde(default = "get_default_active")]
+    active: bool,
+}
+
+fn get_default_jitter() -> i64{
+    0
+}
+
+fn get_default_catch_up() -> CatchUp{
+    CatchUp::Skip
+}
+
+fn get_default_max_catch_up() -> i64{
+    10
+}
+
+fn get_default_active() -> bool{
+    true
+}
+
+impl CatchUp{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            Self::Skip => "skip",
+            Self::Once => "once",
+            Self::All => "all",
+        }
+    }
+}
+
+impl FromStr for CatchUp{
+    type Err = CustomError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s{
+            "skip" => Ok(Self::Skip),
+            "once" => Ok(Self::Once),
+            "all" => Ok(Self::All),
+            _ => Err(CustomError::BadRequest),
+        }
+    }
+}
+
+/// Parses a cron expression. Classic five field expressions
+/// (minute hour day month weekday) are accepted as well as the six or
+/// seven field ones that start with the seconds.
+fn parse_cron(expression: &str) -> Result<cron::Schedule, CustomError>{
+    let expression = expression.trim();
+    let expression = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    }else{
+        expression.to_string()
+    };
+    cron::Schedule::from_str(&expression)
+        .map_err(|e| {
+            tracing::error!("Invalid cron expression: {}", e);
+            CustomError::BadRequest
+        })
+}
+
+fn check_schedule(errors: &mut Errors, cron: &str, jitter: i64, max_catch_up: i64){
+    if parse_cron(cron).is_err() {
+        errors.add("cron", "is not a valid cron expression".to_string());
+    }
+    if jitter < 0 {
+        errors.add("jitter", "can't be negative".to_string());
+    }
+    if max_catch_up < 1 {
+        errors.add("max_catch_up", "has to be at least 1".to_string());
+    }
+}
+
+impl Validate for NewSchedule{
+    fn check(&self, errors: &mut Errors){
+        check_schedule(errors, &self.cron, self.jitter, self.max_catch_up);
+    }
+}
+
+impl Validate for Schedule{
+    fn check(&self, errors: &mut Errors){
+        check_schedule(errors, &self.cron, self.jitter, self.max_catch_up);
+    }
+}
+
+impl NewSchedule{
+    pub fn get_category_id(&self) -> i64{
+        self.category_id
+    }
+}
+
+impl Schedule{
+    /// Reads a schedule, a kind or a catch up policy that is not known is an
+    /// error, it would publish something that was not asked for
+    fn from_row(row: SqliteRow) -> Result<Self, sqlx::Error>{
+        Ok(Self{
+            id: row.get("id"),
+            category_id: row.get("category_id"),
+            kind: decode(&row, "kind")?,
+            cron: row.get("cron"),
+            jitter: row.get("jitter"),
+            catch_up: decode(&row, "catch_up")?,
+            max_catch_up: row.get("max_catch_up"),
+            active: row.get("active"),
+            last_run: row.get("last_run"),
+        })
+    }
+
+    pub fn get_id(&self) -> i64{
+        self.id
+    }
+
+    pub fn get_category_id(&self) -> i64{
+        self.category_id
+    }
+
+    pub fn get_kind(&self) -> Kind{
+        self.kind
+    }
+
+    pub fn get_jitter(&self) -> i64{
+        self.jitter
+    }
+
+    /// Returns how many times the schedule has to run now, taking into
+    /// account the runs between `last_run` and `now` and the catch up
+    /// policy. Runs as old as `grace` or older are considered missed. Only
+    /// the runs that can be caught up are looked for, however long the
+    /// server has been down.
+    pub fn pending_runs(&self, now: DateTime<Utc>, grace: Duration) -> Result<usize, CustomError>{
+        let last_run = match self.last_run{
+            Some(last_run) => last_run,
+            None => return Ok(0),
+        };
+        let cron = parse_cron(&self.cron)?;
+        let runs_after = |start: DateTime<Utc>| cron.after(&start)
+            .take_while(|run| run <= &now);
+        Ok(match self.catch_up{
+            CatchUp::Skip => runs_after(last_run.max(now - grace)).take(1).count(),
+            CatchUp::Once => runs_after(last_run).take(1).count(),
+            CatchUp::All => runs_after(last_run).take(self.max_catch_up.max(1) as usize).count(),
+        })
+    }
+
+    pub async fn create(pool: &SqlitePool, new_schedule: NewSchedule)
+            -> Result<Schedule, CustomError>{
+        tracing::info!("Data: {:?}", new_schedule);
+        new_schedule.validate()?;
+        let sql = "INSERT INTO schedules (category_id, kind, cron, jitter,
+                   catch_up, max_catch_up, active, last_run)
+                   VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *;";
+        query(sql)
+            .bind(new_schedule.category_id)
+            .bind(new_schedule.kind.as_str())
+            .bind(new_schedule.cron)
+            .bind(new_schedule.jitter)
+            .bind(new_schedule.catch_up.as_str())
+            .bind(new_schedule.max_catch_up)
+            .bind(new_schedule.active)
+            .bind(Utc::now())
+            .try_map(Self::from_row)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    pub async fn read(pool: &SqlitePool, id: i64) -> Result<Schedule, CustomError>{
+        let sql = "SELECT * FROM schedules WHERE id = $1";
+        query(sql)
+            .bind(id)
+            .try_map(Self::from_row)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| match e{
+                sqlx::Error::RowNotFound => CustomError::NotFound,
+                e => CustomError::ServerError(e.to_string()),
+            })
+    }
+
+    pub async fn read_all(pool: &SqlitePool) -> Result<Vec<Schedule>, CustomError>{
+        let sql = "SELECT * FROM schedules";
+        query(sql)
+            .try_map(Self::from_row)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    pub async fn read_active(pool: &SqlitePool) -> Result<Vec<Schedule>, CustomError>{
+        let sql = "SELECT * FROM schedules WHERE active = TRUE ORDER BY id";
+        query(sql)
+            .try_map(Self::from_row)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    pub async fn set_last_run(pool: &SqlitePool, id: i64, last_run: DateTime<Utc>) -> Result<(), CustomError>{
+        let sql = "UPDATE schedules SET last_run = $2 WHERE id = $1";
+        query(sql)
+            .bind(id)
+            .bind(last_run)
+            .execute(pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    pub async fn update(pool: &SqlitePool, schedule: Schedule) -> Result<Schedule, CustomError>{
+        schedule.validate()?;
+        let last_run = schedule.last_run.unwrap_or_else(Utc::now);
+        let sql = "UPDATE schedules SET category_id = $2, kind = $3, cron = $4,
+                   jitter = $5, catch_up = $6, max_catch_up = $7, active = $8,
+                   last_run = $9 WHERE id = $1 RETURNING * ;";
+        query(sql)
+            .bind(schedule.id)
+            .bind(schedule.category_id)
+            .bind(schedule.kind.as_str())
+            .bind(schedule.cron)
+            .bind(schedule.jitter)
+            .bind(schedule.catch_up.as_str())
+            .bind(schedule.max_catch_up)
+            .bind(schedule.active)
+            .bind(last_run)
+            .try_map(Self::from_row)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error: {}", e);
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    pub async fn delete(pool: &SqlitePool, id: i64) -> Result<Schedule, CustomError>{
+        let sql = "DELETE from schedules WHERE id = $1 RETURNING * ;";
+        query(sql)
+            .bind(id)
+            .try_map(Self::from_row)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use serde_json::json;
+    use crate::database;
+    use super::*;
+
+    fn schedule(cron: &str, catch_up: CatchUp, last_run: DateTime<Utc>) -> Schedule{
+        serde_json::from_value(json!({
+            "id": 1,
+            "category_id": 1,
+            "kind": "tip",
+            "cron": cron,
+            "catch_up": catch_up,
+            "max_catch_up": 3,
+            "last_run": last_run,
+        })).unwrap()
+    }
+
+    fn at(time: &str) -> DateTime<Utc>{
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    const GRACE: i64 = 15;
+
+    #[test]
+    fn skip_runs_only_when_on_time(){
+        let schedule = schedule("0 * * * *", CatchUp::Skip, at("2023-07-01T00:00:00Z"));
+        let grace = Duration::minutes(GRACE);
+
+        assert_eq!(schedule.pending_runs(at("2023-07-01T05:10:00Z"), grace).unwrap(), 1);
+        assert_eq!(schedule.pending_runs(at("2023-07-01T05:30:00Z"), grace).unwrap(), 0);
+        assert_eq!(schedule.pending_runs(at("2023-07-01T00:30:00Z"), grace).unwrap(), 0);
+    }
+
+    #[test]
+    fn once_runs_a_single_time_for_all_the_missed_runs(){
+        let schedule = schedule("0 * * * *", CatchUp::Once, at("2023-07-01T00:00:00Z"));
+        let grace = Duration::minutes(GRACE);
+
+        assert_eq!(schedule.pending_runs(at("2023-07-01T05:30:00Z"), grace).unwrap(), 1);
+        assert_eq!(schedule.pending_runs(at("2023-07-01T00:30:00Z"), grace).unwrap(), 0);
+    }
+
+    #[test]
+    fn all_runs_the_missed_runs_up_to_the_maximum(){
+        let schedule = schedule("0 * * * *", CatchUp::All, at("2023-07-01T00:00:00Z"));
+        let grace = Duration::minutes(GRACE);
+
+        assert_eq!(schedule.pending_runs(at("2023-07-01T02:30:00Z"), grace).unwrap(), 2);
+        assert_eq!(schedule.pending_runs(at("2023-07-01T05:30:00Z"), grace).unwrap(), 3);
+        // Every second for a year, only the maximum is looked for
+        let schedule = Schedule{
+            cron: "* * * * * *".to_string(),
+            ..schedule
+        };
+        assert_eq!(schedule.pending_runs(at("2024-07-01T00:00:00Z"), grace).unwrap(), 3);
+    }
+
+    #[test]
+    fn never_run_schedules_wait_for_the_next_run(){
+        let schedule = Schedule{
+            last_run: None,
+            ..schedule("0 * * * *", CatchUp::All, at("2023-07-01T00:00:00Z"))
+        };
+
+        assert_eq!(schedule.pending_runs(at("2023-07-01T05:30:00Z"), Duration::minutes(GRACE)).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn an_unknown_kind_is_an_error(){
+        let pool = database::memory().await;
+        query("INSERT INTO schedules (category_id, kind, cron) VALUES (1, 'video', '0 * * * *')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        match Schedule::read(&pool, 1).await{
+            Err(CustomError::ServerError(e)) => assert!(e.contains("unknown kind video"), "{}", e),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use serde::{Serialize, Deserialize};
+use sqlx::{sqlite::{SqlitePool, SqliteRow}, query, Row};
+use super::{
+    decode,
+    kind::Kind,
+    listing::MAX_LIMIT,
+    validation::Errors,
+    error::CustomError,
+};
+
+const DEFAULT_LIMIT: i64 = 20;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SearchParams{
+    #[serde(default)]
+    pub q: String,
+    pub category_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// A tip or a poll matching the search, the best ones have the lowest rank
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Hit{
+    kind: Kind,
+    id: i64,
+    category_id: i64,
+    /// Title of the tip or question of the poll
+    title: String,
+    /// Fragment of the content with the words found in bold
+    snippet: String,
+    rank: f64,
+}
+
+/// Each word of the search has to be in the content, as a whole word or as
+/// the beginning of one. The words are quoted so the syntax of FTS5 is not
+/// taken from the user.
+fn to_match(q: &str) -> String{
+    q.split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+impl Hit{
+    fn from_row(row: SqliteRow) -> Result<Self, sqlx::Error>{
+        Ok(Self{
+            kind: decode(&row, "kind")?,
+            id: row.get("id"),
+            category_id: row.get("category_id"),
+            title: row.get("title"),
+            snippet: row.get("snippet"),
+            rank: row.get("rank"),
+        })
+    }
+
+    /// Searches the tips, by title and text, and the polls, by question and
+    /// answers, sorted by relevance
+    pub async fn search(pool: &SqlitePool, params: &SearchParams) -> Result<Vec<Hit>, CustomError>{
+        let mut errors = Errors::default();
+        errors.required("q", &params.q);
+        errors.into_result()?;
+        let sql = "SELECT 'tip' AS kind, tips.id, tips.category_id, tips.title AS title,
+                          snippet(tips_fts, -1, '<b>', '</b>', '…', 16) AS snippet,
+                          bm25(tips_fts, 2.0, 1.0) AS rank
+                   FROM tips_fts JOIN tips ON tips.id = tips_fts.rowid
+                   WHERE tips_fts MATCH $1 AND ($2 IS NULL OR tips.category_id = $2)
+                   UNION ALL
+                   SELECT 'poll' AS kind, polls.id, polls.category_id, polls.question AS title,
+                          snippet(polls_fts, -1, '<b>', '</b>', '…', 16) AS snippet,
+                          bm25(polls_fts, 2.0, 1.0) AS rank
+                   FROM polls_fts JOIN polls ON polls.id = polls_fts.rowid
+                   WHERE polls_fts MATCH $1 AND ($2 IS NULL OR polls.category_id = $2)
+                   ORDER BY rank LIMIT $3";
+        query(sql)
+            .bind(to_match(&params.q))
+            .bind(params.category_id)
+            .bind(params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT))
+            .try_map(Self::from_row)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::database;
+    use super::*;
+
+    async fn found(pool: &SqlitePool, q: &str) -> Vec<(Kind, i64)>{
+        let params = SearchParams{
+            q: q.to_string(),
+            ..SearchParams::default()
+        };
+        Hit::search(pool, &params)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|hit| (hit.kind, hit.id))
+            .collect()
+    }
+
+    async fn execute(pool: &SqlitePool, sql: &str){
+        query(sql).execute(pool).await.unwrap();
+    }
+
+    #[test]
+    fn quotes_the_words_of_the_search(){
+        assert_eq!(to_match("borrow checker"), r#""borrow"* "checker"*"#);
+        assert_eq!(to_match("say \"hi"), r#""say"* """hi"*"#);
+        assert_eq!(to_match("rust AND go"), r#""rust"* "AND"* "go"*"#);
+        assert_eq!(to_match("title:x NEAR(a b)"), r#""title:x"* "NEAR(a"* "b)"*"#);
+    }
+
+    #[tokio::test]
+    async fn the_syntax_of_fts_is_taken_as_text(){
+        let pool = database::memory().await;
+        execute(&pool, "INSERT INTO tips (category_id, title, text) VALUES (1, 'Say \"hi\"', 'AND or NOT')").await;
+
+        assert_eq!(found(&pool, "\"hi").await, vec![(Kind::Tip, 1)]);
+        assert_eq!(found(&pool, "AND").await, vec![(Kind::Tip, 1)]);
+        assert_eq!(found(&pool, "text:NOT").await, vec![]);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_index_of_the_tips(){
+        let pool = database::memory().await;
+        execute(&pool, "INSERT INTO tips (category_id, title, text) VALUES (1, 'Borrow', 'Only one mutable reference')").await;
+        assert_eq!(found(&pool, "mutable").await, vec![(Kind::Tip, 1)]);
+
+        execute(&pool, "UPDATE tips SET text = 'Lifetimes everywhere' WHERE id = 1").await;
+        assert_eq!(found(&pool, "mutable").await, vec![]);
+        assert_eq!(found(&pool, "lifetime").await, vec![(Kind::Tip, 1)]);
+
+        execute(&pool, "DELETE FROM tips WHERE id = 1").await;
+        assert_eq!(found(&pool, "lifetime").await, vec![]);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_answers_in_the_index_of_the_polls(){
+        let pool = database::memory().await;
+        execute(&pool, "INSERT INTO polls (category_id, question) VALUES (1, 'Which one moves?')").await;
+        execute(&pool, "INSERT INTO polls (category_id, question) VALUES (1, 'Which one copies?')").await;
+        execute(&pool, "INSERT INTO answers (poll_id, text, isok) VALUES (1, 'String', true)").await;
+        execute(&pool, "INSERT INTO answers (poll_id, text, isok) VALUES (1, 'Vector', false)").await;
+        assert_eq!(found(&pool, "string").await, vec![(Kind::Poll, 1)]);
+        assert_eq!(found(&pool, "vector").await, vec![(Kind::Poll, 1)]);
+
+        execute(&pool, "UPDATE answers SET text = 'Box' WHERE id = 1").await;
+        assert_eq!(found(&pool, "string").await, vec![]);
+        assert_eq!(found(&pool, "box").await, vec![(Kind::Poll, 1)]);
+        assert_eq!(found(&pool, "vector").await, vec![(Kind::Poll, 1)]);
+
+        // Moved to another poll, both of them are indexed again
+        execute(&pool, "UPDATE answers SET poll_id = 2 WHERE id = 1").await;
+        assert_eq!(found(&pool, "box").await, vec![(Kind::Poll, 2)]);
+
+        execute(&pool, "DELETE FROM answers WHERE id = 2").await;
+        assert_eq!(found(&pool, "vector").await, vec![]);
+
+        execute(&pool, "UPDATE polls SET question = 'Which one borrows?' WHERE id = 2").await;
+        assert_eq!(found(&pool, "copies").await, vec![]);
+        assert_eq!(found(&pool, "borrows box").await, vec![(Kind::Poll, 2)]);
+
+        execute(&pool, "DELETE FROM polls WHERE id = 2").await;
+        assert_eq!(found(&pool, "borrows").await, vec![]);
+    }
+}
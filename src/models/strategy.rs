@@ -0,0 +1,210 @@
+use std::{fmt, str::FromStr};
+use chrono::{DateTime, Utc};
+use rand::{Rng, distributions::{Distribution, WeightedIndex}};
+use serde::{Serialize, Deserialize};
+use super::error::CustomError;
+
+/// How the next item of a category is chosen among the ones not published
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy{
+    /// In the order they were created
+    #[default]
+    Fifo,
+    /// Any of them, with the same chance
+    Random,
+    /// The one with the highest priority, the oldest one on a tie
+    Priority,
+    /// Any of them, with more chance the higher the priority. Every item
+    /// has a chance, a priority of 0 or less counts as 1.
+    Weighted,
+}
+
+/// An item that can be chosen to be published
+pub trait Candidate{
+    fn get_id(&self) -> i64;
+    fn get_priority(&self) -> i64;
+    /// When it was scheduled for, if it was
+    fn get_publish_at(&self) -> Option<DateTime<Utc>>;
+}
+
+impl Strategy{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            Self::Fifo => "fifo",
+            Self::Random => "random",
+            Self::Priority => "priority",
+            Self::Weighted => "weighted",
+        }
+    }
+
+    /// Whether the next item depends on chance
+    pub fn is_random(&self) -> bool{
+        matches!(self, Self::Random | Self::Weighted)
+    }
+
+    /// Sorts the items in the order they would be published, or by their
+    /// chance when it is random. The scheduled items go first, by date,
+    /// whatever the strategy.
+    pub fn sort<T: Candidate>(&self, items: &mut [T]){
+        match self{
+            Self::Fifo | Self::Random => items.sort_by_key(|item|
+                (item.get_publish_at().is_none(), item.get_publish_at(), item.get_id())),
+            Self::Priority | Self::Weighted => items.sort_by_key(|item|
+                (item.get_publish_at().is_none(), item.get_publish_at(), -item.get_priority(),
+                 item.get_id())),
+        }
+    }
+
+    /// Chooses the next item to publish. The items are expected to be due,
+    /// so a scheduled one is taken before the strategy is applied.
+    pub fn choose<T: Candidate, R: Rng>(&self, mut items: Vec<T>, rng: &mut R) -> Option<T>{
+        if items.is_empty() {
+            return None;
+        }
+        self.sort(&mut items);
+        if items[0].get_publish_at().is_some() {
+            return Some(items.swap_remove(0));
+        }
+        let index = match self{
+            Self::Fifo | Self::Priority => 0,
+            Self::Random => rng.gen_range(0..items.len()),
+            Self::Weighted => {
+                let weights = items.iter().map(|item| item.get_priority().max(1));
+                WeightedIndex::new(weights)
+                    .map(|distribution| distribution.sample(rng))
+                    .unwrap_or(0)
+            },
+        };
+        Some(items.swap_remove(index))
+    }
+}
+
+impl fmt::Display for Strategy{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Strategy{
+    type Err = CustomError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s{
+            "fifo" => Ok(Self::Fifo),
+            "random" => Ok(Self::Random),
+            "priority" => Ok(Self::Priority),
+            "weighted" => Ok(Self::Weighted),
+            _ => Err(CustomError::BadRequest),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use chrono::TimeZone;
+    use rand::{SeedableRng, rngs::StdRng};
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Item{
+        id: i64,
+        priority: i64,
+        publish_at: Option<DateTime<Utc>>,
+    }
+
+    impl Candidate for Item{
+        fn get_id(&self) -> i64{
+            self.id
+        }
+
+        fn get_priority(&self) -> i64{
+            self.priority
+        }
+
+        fn get_publish_at(&self) -> Option<DateTime<Utc>>{
+            self.publish_at
+        }
+    }
+
+    fn item(id: i64, priority: i64) -> Item{
+        Item{id, priority, publish_at: None}
+    }
+
+    fn scheduled(id: i64, day: u32) -> Item{
+        Item{
+            id,
+            priority: 0,
+            publish_at: Some(Utc.with_ymd_and_hms(2023, 7, day, 12, 0, 0).unwrap()),
+        }
+    }
+
+    /// Ids chosen in `draws` choices among the same items
+    fn draw(strategy: Strategy, items: &[Item], draws: usize, seed: u64) -> Vec<i64>{
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..draws)
+            .map(|_| strategy.choose(items.to_vec(), &mut rng).unwrap().id)
+            .collect()
+    }
+
+    #[test]
+    fn nothing_to_choose_from(){
+        let mut rng = StdRng::seed_from_u64(1);
+        for strategy in [Strategy::Fifo, Strategy::Random, Strategy::Priority, Strategy::Weighted]{
+            assert_eq!(strategy.choose(Vec::<Item>::new(), &mut rng), None);
+        }
+    }
+
+    #[test]
+    fn fifo_takes_the_oldest(){
+        let items = vec![item(3, 0), item(1, 0), item(2, 9)];
+        assert_eq!(draw(Strategy::Fifo, &items, 5, 1), vec![1; 5]);
+    }
+
+    #[test]
+    fn priority_takes_the_highest_and_the_oldest_on_a_tie(){
+        let items = vec![item(1, 1), item(4, 5), item(2, 5), item(3, -2)];
+        assert_eq!(draw(Strategy::Priority, &items, 5, 1), vec![2; 5]);
+    }
+
+    #[test]
+    fn random_is_repeatable_with_a_seed_and_takes_any(){
+        let items: Vec<Item> = (1..=4).map(|id| item(id, 0)).collect();
+        let chosen = draw(Strategy::Random, &items, 100, 42);
+        assert_eq!(chosen, draw(Strategy::Random, &items, 100, 42));
+        assert_ne!(chosen, draw(Strategy::Random, &items, 100, 43));
+        for id in 1..=4 {
+            assert!(chosen.contains(&id), "{} is never chosen", id);
+        }
+    }
+
+    #[test]
+    fn weighted_favours_the_highest_priority(){
+        let items = vec![item(1, 0), item(2, 1), item(3, 8)];
+        let chosen = draw(Strategy::Weighted, &items, 1000, 42);
+        assert_eq!(chosen, draw(Strategy::Weighted, &items, 1000, 42));
+        let count = |id| chosen.iter().filter(|chosen| **chosen == id).count();
+        // The weights are 1, 1 and 8
+        assert!(count(3) > 700, "{}", count(3));
+        assert!(count(1) > 50 && count(2) > 50, "{} {}", count(1), count(2));
+    }
+
+    #[test]
+    fn scheduled_goes_first_whatever_the_strategy(){
+        let items = vec![item(1, 9), scheduled(2, 3), scheduled(3, 1), item(4, 0)];
+        for strategy in [Strategy::Fifo, Strategy::Random, Strategy::Priority, Strategy::Weighted]{
+            assert_eq!(draw(strategy, &items, 20, 7), vec![3; 20], "{}", strategy);
+        }
+    }
+
+    #[test]
+    fn sort_puts_the_scheduled_first_by_date(){
+        let mut items = vec![item(1, 0), scheduled(2, 3), item(3, 5), scheduled(4, 1)];
+        Strategy::Priority.sort(&mut items);
+        let ids: Vec<i64> = items.iter().map(|item| item.id).collect();
+        assert_eq!(ids, vec![4, 2, 3, 1]);
+        Strategy::Fifo.sort(&mut items);
+        let ids: Vec<i64> = items.iter().map(|item| item.id).collect();
+        assert_eq!(ids, vec![4, 2, 1, 3]);
+    }
+}
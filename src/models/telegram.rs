@@ -1,81 +1,418 @@
-use reqwest::Client;
-use serde_json::json;
-use tracing::{info, error};
+use std::{fmt, sync::Arc, time::{Duration, Instant}};
+use async_trait::async_trait;
+use reqwest::{Client, Response};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde_json::{json, Value};
+use tracing::{info, warn, error};
 
-use super::error::CustomError;
+use super::{
+    category::Category,
+    publisher::{Publisher, Message, Quiz, Receipt},
+    rate_limit::{Limits, RateLimiter, DEFAULT_LIMITS},
+    error::CustomError,
+};
 
-#[derive(Debug)]
+pub const DEFAULT_BASE_URL: &str = "https://api.telegram.org";
+/// Maximum time to establish the connection with Telegram
+const CONNECT_TIMEOUT: u64 = 10;
+/// Maximum time for the whole request
+const TIMEOUT: u64 = 30;
+/// Seconds to keep retrying a message that can't be sent
+pub const DEFAULT_RETRY_BUDGET: u64 = 60;
+/// Wait before the first retry after a failure, it doubles with each one
+const FIRST_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
 pub struct Telegram {
+    client: Client,
+    base_url: String,
     token: String,
+    /// Shared by the clones, so every message counts for the limits
+    limiter: Arc<RateLimiter>,
+    retry_budget: Duration,
+}
+
+/// Envelope of every response of the Bot API
+#[derive(Debug, Deserialize)]
+struct ApiResponse<T>{
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+    error_code: Option<u16>,
+    parameters: Option<ResponseParameters>,
+}
+
+/// Extra information about why a request failed
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ResponseParameters{
+    pub migrate_to_chat_id: Option<i64>,
+    pub retry_after: Option<u64>,
+}
+
+/// The part of a sent message we need
+#[derive(Debug, Deserialize)]
+pub struct SentMessage{
+    pub message_id: i64,
+}
+
+/// Why Telegram refused a request
+#[derive(Debug)]
+pub enum TelegramError{
+    /// The request is not valid, the text or the chat are wrong
+    BadRequest(String),
+    /// The token of the bot is not valid
+    Unauthorized(String),
+    /// The bot can't write in the chat, it has been kicked or blocked
+    Forbidden(String),
+    /// The group has been upgraded to a supergroup with a new id
+    ChatMigrated(i64),
+    /// Flood control, the request can be repeated after these seconds
+    TooManyRequests(u64),
+    /// Telegram failed, the request can be repeated
+    ServerError(u16, String),
+    /// Telegram couldn't be reached or its response couldn't be read
+    Network(String),
+}
+
+impl fmt::Display for TelegramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self{
+            // Telegram already starts these descriptions with the reason
+            Self::BadRequest(e) => write!(f, "{}", e),
+            Self::Unauthorized(e) => write!(f, "{}", e),
+            Self::Forbidden(e) => write!(f, "{}", e),
+            Self::ChatMigrated(chat_id) => write!(f, "Chat migrated to {}", chat_id),
+            Self::TooManyRequests(retry_after) => write!(f, "Too many requests, retry after {} seconds", retry_after),
+            Self::ServerError(code, e) => write!(f, "Error {}: {}", code, e),
+            Self::Network(e) => write!(f, "Network error: {}", e),
+        }
+    }
+}
+
+impl<T> ApiResponse<T>{
+    /// Gets the result or the error explained by Telegram
+    fn into_result(self) -> Result<T, TelegramError>{
+        if self.ok {
+            return self.result.ok_or_else(|| TelegramError::Network(
+                "Response without result".to_string()));
+        }
+        let description = self.description.unwrap_or_default();
+        let parameters = self.parameters.unwrap_or_default();
+        if let Some(chat_id) = parameters.migrate_to_chat_id {
+            return Err(TelegramError::ChatMigrated(chat_id));
+        }
+        Err(match self.error_code{
+            Some(400) => TelegramError::BadRequest(description),
+            Some(401) => TelegramError::Unauthorized(description),
+            Some(403) => TelegramError::Forbidden(description),
+            Some(429) => TelegramError::TooManyRequests(parameters.retry_after.unwrap_or(1)),
+            code => TelegramError::ServerError(code.unwrap_or(500), description),
+        })
+    }
+}
+
+/// The wait before the next retry, doubling the one after
+fn next_backoff(backoff: &mut Duration) -> Duration{
+    let delay = *backoff;
+    *backoff = (delay * 2).min(MAX_BACKOFF);
+    delay
 }
 
 impl Telegram {
-    pub fn new(token: &str) -> Self{
+    /// Creates a client for the Bot API served at `base_url`, usually
+    /// `DEFAULT_BASE_URL`, but it can be a local server or a mock
+    pub fn new(token: &str, base_url: &str) -> Self{
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT))
+            .timeout(Duration::from_secs(TIMEOUT))
+            .build()
+            .expect("Can't build the HTTP client");
         Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
             token: token.to_string(),
+            limiter: Arc::new(RateLimiter::new(DEFAULT_LIMITS)),
+            retry_budget: Duration::from_secs(DEFAULT_RETRY_BUDGET),
         }
     }
 
-    pub async fn send_message(&self, chat_id: &str, thread_id: i64, message: &str) -> Result<String, CustomError>{
-        tracing::debug!("Send message");
-        let url = format!("https://api.telegram.org/bot{}/sendMessage",
-            self.token);
-        let message = json!({
-            "chat_id": chat_id,
-            "message_thread_id": thread_id,
-            "text": message,
-            "parse_mode": "HTML",
-        });
-        tracing::debug!("Message: {}", &message);
-        match Client::new()
-            .post(url)
-            .json(&message)
-            .send()
-            .await{
-                Ok(response) => {
-                    info!("Mensaje envíado a Telegram: {}",
-                        response.status().to_string());
-                    Ok(response.status().to_string())
-                },
-                Err(error) => {
-                    error!("No he podido enviar el mensaje a Telegram: {}",
-                        error.to_string());
-                    Err(CustomError::OtherError(error.to_string()))
+    pub fn with_limits(mut self, limits: Limits) -> Self{
+        self.limiter = Arc::new(RateLimiter::new(limits));
+        self
+    }
+
+    /// Time to keep retrying a message, counting the waits. Zero disables the
+    /// retries.
+    pub fn with_retry_budget(mut self, retry_budget: Duration) -> Self{
+        self.retry_budget = retry_budget;
+        self
+    }
+
+    /// Calls a method of the Bot API to send to the chat, waiting for its turn
+    /// within the limits of Telegram. When the limits are exceeded anyway it is
+    /// repeated after the time Telegram asks for, and when Telegram can't be
+    /// reached or fails, with exponential backoff, while it is within the
+    /// budget. A message may be sent twice if Telegram got it but its answer
+    /// was lost.
+    async fn call<T: DeserializeOwned>(&self, chat_id: &str, method: &str, body: &Value) -> Result<T, TelegramError>{
+        let url = format!("{}/bot{}/{}", self.base_url, self.token, method);
+        tracing::debug!("Message: {}", body);
+        let started = Instant::now();
+        let mut backoff = FIRST_BACKOFF;
+        loop{
+            self.limiter.wait(chat_id).await;
+            let (error, delay) = match self.client.post(&url).json(body).send().await{
+                Ok(response) => match Self::read(response).await{
+                    Ok(result) => return Ok(result),
+                    Err(TelegramError::TooManyRequests(retry_after)) => (
+                        TelegramError::TooManyRequests(retry_after),
+                        Duration::from_secs(retry_after)),
+                    Err(TelegramError::ServerError(code, e)) if code >= 500 => (
+                        TelegramError::ServerError(code, e),
+                        next_backoff(&mut backoff)),
+                    Err(e) => return Err(e),
                 },
+                Err(e) => (TelegramError::Network(e.without_url().to_string()),
+                    next_backoff(&mut backoff)),
+            };
+            if started.elapsed() + delay > self.retry_budget {
+                return Err(error);
             }
+            warn!("{}, {} again in {:?}", error, method, delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Reads the answer of the Bot API
+    async fn read<T: DeserializeOwned>(response: Response) -> Result<T, TelegramError>{
+        let status = response.status();
+        response.json::<ApiResponse<T>>()
+            .await
+            .map_err(|e| if status.is_success(){
+                TelegramError::Network(e.without_url().to_string())
+            }else{
+                TelegramError::ServerError(status.as_u16(), e.without_url().to_string())
+            })?
+            .into_result()
+    }
+
+    /// Sends an HTML message and returns its id
+    pub async fn send_message(&self, chat_id: &str, thread_id: i64, message: &str) -> Result<i64, CustomError>{
+        tracing::debug!("Send message");
+        let message = message_body(chat_id, thread_id, message);
+        match self.call::<SentMessage>(chat_id, "sendMessage", &message).await{
+            Ok(sent) => {
+                info!("Mensaje envíado a Telegram: {}", sent.message_id);
+                Ok(sent.message_id)
+            },
+            Err(e) => {
+                error!("No he podido enviar el mensaje a Telegram: {}", e);
+                Err(CustomError::Telegram(e))
+            },
+        }
     }
 
-    pub async fn send_poll(&self, chat_id: &str, thread_id: i64, question: &str, options: Vec<&str>, correct_option_id: i64) -> Result<String, CustomError>{
+    /// Sends a quiz and returns the id of its message
+    pub async fn send_poll(&self, chat_id: &str, thread_id: i64, question: &str, options: Vec<&str>, correct_option_id: i64) -> Result<i64, CustomError>{
         tracing::debug!("Send poll");
-        let url = format!("https://api.telegram.org/bot{}/sendPoll",
-            self.token);
-        let message = json!({
-            "chat_id": chat_id,
-            "message_thread_id": thread_id,
-            "question": question,
-            "options": options,
-            "is_anonymous": true,
-            "type": "quiz",
-            "allows_ultiple_answers": false,
-            "correct_option_id": correct_option_id,
-            "parse_mode": "HTML",
-        });
-        tracing::debug!("Message: {}", &message);
-        match Client::new()
-            .post(url)
-            .json(&message)
-            .send()
-            .await{
-                Ok(response) => {
-                    info!("Mensaje envíado a Telegram: {}",
-                        response.status().to_string());
-                    Ok(response.status().to_string())
-                },
-                Err(error) => {
-                    error!("No he podido enviar el mensaje a Telegram: {}",
-                        error.to_string());
-                    Err(CustomError::OtherError(error.to_string()))
-                },
-            }
+        let message = poll_body(chat_id, thread_id, question, options, correct_option_id);
+        match self.call::<SentMessage>(chat_id, "sendPoll", &message).await{
+            Ok(sent) => {
+                info!("Encuesta envíada a Telegram: {}", sent.message_id);
+                Ok(sent.message_id)
+            },
+            Err(e) => {
+                error!("No he podido enviar la encuesta a Telegram: {}", e);
+                Err(CustomError::Telegram(e))
+            },
+        }
+    }
+}
+
+/// Body of `sendMessage` for an HTML message
+fn message_body(chat_id: &str, thread_id: i64, message: &str) -> Value{
+    let mut body = json!({
+        "chat_id": chat_id,
+        "text": message,
+        "parse_mode": "HTML",
+    });
+    if thread_id > 0 {
+        body["message_thread_id"] = json!(thread_id);
+    }
+    body
+}
+
+/// Body of `sendPoll` for a quiz
+fn poll_body(chat_id: &str, thread_id: i64, question: &str, options: Vec<&str>, correct_option_id: i64) -> Value{
+    let mut body = json!({
+        "chat_id": chat_id,
+        "question": question,
+        "options": options,
+        "is_anonymous": true,
+        "type": "quiz",
+        "allows_multiple_answers": false,
+        "correct_option_id": correct_option_id,
+    });
+    if thread_id > 0 {
+        body["message_thread_id"] = json!(thread_id);
+    }
+    body
+}
+
+#[async_trait]
+impl Publisher for Telegram{
+    async fn send_text(&self, category: &Category, message: &Message) -> Result<Receipt, CustomError>{
+        let message_id = self.send_message(
+            category.get_chat_id(),
+            category.get_thread_id(),
+            &message.html
+        ).await?;
+        Ok(Receipt{
+            message_id: Some(message_id.to_string()),
+        })
+    }
+
+    async fn send_quiz(&self, category: &Category, quiz: &Quiz) -> Result<Receipt, CustomError>{
+        let message_id = self.send_poll(
+            category.get_chat_id(),
+            category.get_thread_id(),
+            &quiz.question,
+            quiz.options.iter().map(|option| option.as_str()).collect(),
+            quiz.correct_option_id as i64
+        ).await?;
+        Ok(Receipt{
+            message_id: Some(message_id.to_string()),
+        })
+    }
+
+    fn preview_text(&self, category: &Category, message: &Message) -> Result<Value, CustomError>{
+        Ok(message_body(category.get_chat_id(), category.get_thread_id(), &message.html))
+    }
+
+    fn preview_quiz(&self, category: &Category, quiz: &Quiz) -> Result<Value, CustomError>{
+        Ok(poll_body(
+            category.get_chat_id(),
+            category.get_thread_id(),
+            &quiz.question,
+            quiz.options.iter().map(|option| option.as_str()).collect(),
+            quiz.correct_option_id as i64))
+    }
+
+    fn max_send_time(&self) -> Duration{
+        self.retry_budget + Duration::from_secs(TIMEOUT)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::stub::Stub;
+    use super::*;
+
+    const TOKEN: &str = "123:secret";
+
+    /// A client for the stub, with limits that don't slow the tests down
+    fn telegram(stub: &Stub) -> Telegram{
+        Telegram::new(TOKEN, &stub.url)
+            .with_limits(Limits{global: 1000, chat: 6000})
+    }
+
+    fn sent(message_id: i64) -> (u16, Value){
+        (200, json!({"ok": true, "result": {"message_id": message_id}}))
+    }
+
+    fn failed(code: u16, description: &str) -> (u16, Value){
+        (code, json!({"ok": false, "error_code": code, "description": description}))
+    }
+
+    #[tokio::test]
+    async fn sends_a_message(){
+        let stub = Stub::start(vec![sent(7)]);
+
+        let message_id = telegram(&stub).send_message("@rust", 3, "<b>Tip</b>").await.unwrap();
+
+        assert_eq!(message_id, 7);
+        let requests = stub.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, format!("/bot{}/sendMessage", TOKEN));
+        assert_eq!(requests[0].body["chat_id"], "@rust");
+        assert_eq!(requests[0].body["message_thread_id"], 3);
+        assert_eq!(requests[0].body["parse_mode"], "HTML");
+    }
+
+    #[tokio::test]
+    async fn waits_the_time_asked_for_when_there_are_too_many_requests(){
+        let stub = Stub::start(vec![
+            (429, json!({"ok": false, "error_code": 429, "description": "Too Many Requests",
+                         "parameters": {"retry_after": 1}})),
+            sent(7),
+        ]);
+
+        let started = Instant::now();
+        let message_id = telegram(&stub).send_message("@rust", 0, "Tip").await.unwrap();
+
+        assert_eq!(message_id, 7);
+        assert_eq!(stub.requests().len(), 2);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn retries_with_backoff_when_telegram_fails(){
+        let stub = Stub::start(vec![
+            failed(502, "Bad Gateway"),
+            failed(500, "Internal Server Error"),
+            sent(7),
+        ]);
+
+        let started = Instant::now();
+        let message_id = telegram(&stub).send_message("@rust", 0, "Tip").await.unwrap();
+
+        assert_eq!(message_id, 7);
+        assert_eq!(stub.requests().len(), 3);
+        // 500 milliseconds after the first failure and twice as long after
+        // the second one
+        assert!(started.elapsed() >= FIRST_BACKOFF * 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_the_retries_run_out(){
+        let stub = Stub::start(vec![failed(500, "Internal Server Error")]);
+
+        let result = telegram(&stub)
+            .with_retry_budget(Duration::from_secs(1))
+            .send_message("@rust", 0, "Tip").await;
+
+        match result{
+            Err(CustomError::Telegram(TelegramError::ServerError(500, _))) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        // The second retry would be after 1.5 seconds
+        assert_eq!(stub.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_a_bad_request(){
+        let stub = Stub::start(vec![failed(400, "Bad Request: chat not found"), sent(7)]);
+
+        let result = telegram(&stub).send_message("@rust", 0, "Tip").await;
+
+        match result{
+            Err(CustomError::Telegram(TelegramError::BadRequest(e))) => assert!(e.contains("chat not found")),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert_eq!(stub.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_token_out_of_network_errors(){
+        let telegram = Telegram::new(TOKEN, "http://127.0.0.1:1")
+            .with_retry_budget(Duration::ZERO);
+
+        let result = telegram.send_message("@rust", 0, "Tip").await;
+
+        match result{
+            Err(CustomError::Telegram(e @ TelegramError::Network(_))) => assert!(!e.to_string().contains("secret")),
+            result => panic!("Unexpected result: {:?}", result),
+        }
     }
 }
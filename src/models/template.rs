@@ -0,0 +1,362 @@
+use minijinja::{context, Environment};
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use sqlx::{sqlite::{SqlitePool, SqliteRow}, query, Row};
+use super::{
+    decode,
+    answer::Answer,
+    category::Category,
+    kind::Kind,
+    poll::Poll,
+    tip::Tip,
+    error::CustomError,
+};
+
+/// Used when the category has no template for tips
+pub const DEFAULT_TIP_TEMPLATE: &str =
+    "<i>Tip</i>: <b>{{ tip.title }}</b>\n\n{{ tip.text }}\n\n{{ hashtag }}";
+/// Used when the category has no template for the question of the polls
+pub const DEFAULT_POLL_TEMPLATE: &str = "{{ poll.question }}\n{{ hashtag }}";
+/// Extended result code of SQLite for a UNIQUE constraint that failed
+const SQLITE_CONSTRAINT_UNIQUE: &str = "2067";
+
+/// Template to render the messages of a kind of content in a category
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Template{
+    id: i64,
+    category_id: i64,
+    kind: Kind,
+    content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewTemplate{
+    category_id: i64,
+    kind: Kind,
+    content: String,
+}
+
+/// Renders a template, the errors are returned as a description of what
+/// is wrong in the template
+fn render<S: Serialize>(content: &str, ctx: S) -> Result<String, CustomError>{
+    let mut env = Environment::new();
+    env.add_template("message", content)
+        .and_then(|_| env.get_template("message"))
+        .and_then(|template| template.render(ctx))
+        .map_err(|e| {
+            tracing::error!("Template error: {}", e);
+            CustomError::Template(e.to_string())
+        })
+}
+
+fn tip_context(tip: &Tip, category: &Category) -> minijinja::value::Value{
+    context!{
+        tip => tip,
+        category => category,
+        hashtag => format!("#{}", category.get_name()),
+    }
+}
+
+fn poll_context(poll: &Poll, answers: &[Answer], category: &Category) -> minijinja::value::Value{
+    context!{
+        poll => poll,
+        answers => answers,
+        category => category,
+        hashtag => format!("#{}", category.get_name()),
+    }
+}
+
+/// Error of a template that couldn't be stored, a category has a template
+/// for each kind of content at most
+fn stored_error(error: sqlx::Error, category_id: i64, kind: Kind) -> CustomError{
+    let unique = error.as_database_error()
+        .and_then(|error| error.code())
+        .map(|code| code == SQLITE_CONSTRAINT_UNIQUE)
+        .unwrap_or(false);
+    if unique {
+        CustomError::Conflict(format!("The category {} already has a template for {}",
+            category_id, kind))
+    }else{
+        CustomError::ServerError(error.to_string())
+    }
+}
+
+/// Checks that the template compiles and can be rendered with sample
+/// content
+fn validate(kind: Kind, content: &str) -> Result<(), CustomError>{
+    let category = json!({
+        "id": 1,
+        "name": "rust",
+        "chat_id": "@rust",
+        "thread_id": 0,
+        "backend": "telegram",
+    });
+    let ctx = match kind{
+        Kind::Tip => context!{
+            tip => json!({
+                "id": 1,
+                "category_id": 1,
+                "title": "Title",
+                "text": "Text",
+                "published": false,
+            }),
+            category => category,
+            hashtag => "#rust",
+        },
+        Kind::Poll => context!{
+            poll => json!({
+                "id": 1,
+                "category_id": 1,
+                "question": "Question?",
+                "published": false,
+            }),
+            answers => json!([
+                {"id": 1, "poll_id": 1, "text": "Yes", "isok": true},
+                {"id": 2, "poll_id": 1, "text": "No", "isok": false},
+            ]),
+            category => category,
+            hashtag => "#rust",
+        },
+    };
+    render(content, ctx).map(|_| ())
+}
+
+impl NewTemplate{
+    pub fn get_category_id(&self) -> i64{
+        self.category_id
+    }
+}
+
+impl Template{
+    /// Reads a template, a kind that is not known is an error, it would
+    /// render the messages of another kind
+    fn from_row(row: SqliteRow) -> Result<Self, sqlx::Error>{
+        Ok(Self{
+            id: row.get("id"),
+            category_id: row.get("category_id"),
+            kind: decode(&row, "kind")?,
+            content: row.get("content"),
+        })
+    }
+
+    pub fn get_category_id(&self) -> i64{
+        self.category_id
+    }
+
+    /// Renders the message for a tip with the template of its category
+    pub async fn render_tip(pool: &SqlitePool, tip: &Tip, category: &Category) -> Result<String, CustomError>{
+        let template = Self::read_for(pool, category.get_id(), Kind::Tip).await?;
+        let content = template.as_ref()
+            .map(|template| template.content.as_str())
+            .unwrap_or(DEFAULT_TIP_TEMPLATE);
+        render(content, tip_context(tip, category))
+    }
+
+    /// Renders the question for a poll with the template of its category
+    pub async fn render_poll(pool: &SqlitePool, poll: &Poll, answers: &[Answer], category: &Category) -> Result<String, CustomError>{
+        let template = Self::read_for(pool, category.get_id(), Kind::Poll).await?;
+        let content = template.as_ref()
+            .map(|template| template.content.as_str())
+            .unwrap_or(DEFAULT_POLL_TEMPLATE);
+        render(content, poll_context(poll, answers, category))
+    }
+
+    pub async fn create(pool: &SqlitePool, new_template: NewTemplate)
+            -> Result<Template, CustomError>{
+        tracing::info!("Data: {:?}", new_template);
+        validate(new_template.kind, &new_template.content)?;
+        let sql = "INSERT INTO templates (category_id, kind, content)
+                   VALUES ($1, $2, $3) RETURNING *;";
+        query(sql)
+            .bind(new_template.category_id)
+            .bind(new_template.kind.as_str())
+            .bind(new_template.content)
+            .try_map(Self::from_row)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| stored_error(e, new_template.category_id, new_template.kind))
+    }
+
+    pub async fn read(pool: &SqlitePool, id: i64) -> Result<Template, CustomError>{
+        let sql = "SELECT * FROM templates WHERE id = $1";
+        query(sql)
+            .bind(id)
+            .try_map(Self::from_row)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| match e{
+                sqlx::Error::RowNotFound => CustomError::NotFound,
+                e => CustomError::ServerError(e.to_string()),
+            })
+    }
+
+    pub async fn read_for(pool: &SqlitePool, category_id: i64, kind: Kind) -> Result<Option<Template>, CustomError>{
+        let sql = "SELECT * FROM templates WHERE category_id = $1 AND kind = $2";
+        query(sql)
+            .bind(category_id)
+            .bind(kind.as_str())
+            .try_map(Self::from_row)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    pub async fn read_all(pool: &SqlitePool) -> Result<Vec<Template>, CustomError>{
+        let sql = "SELECT * FROM templates ORDER BY category_id, kind";
+        query(sql)
+            .try_map(Self::from_row)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    pub async fn update(pool: &SqlitePool, template: Template) -> Result<Template, CustomError>{
+        validate(template.kind, &template.content)?;
+        let sql = "UPDATE templates SET category_id = $2, kind = $3, content = $4
+                   WHERE id = $1 RETURNING * ;";
+        query(sql)
+            .bind(template.id)
+            .bind(template.category_id)
+            .bind(template.kind.as_str())
+            .bind(template.content)
+            .try_map(Self::from_row)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error: {}", e);
+                stored_error(e, template.category_id, template.kind)
+            })?
+            .ok_or(CustomError::NotFound)
+    }
+
+    pub async fn delete(pool: &SqlitePool, id: i64) -> Result<Template, CustomError>{
+        let sql = "DELETE from templates WHERE id = $1 RETURNING * ;";
+        query(sql)
+            .bind(id)
+            .try_map(Self::from_row)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })?
+            .ok_or(CustomError::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::database;
+    use super::*;
+
+    fn category() -> Category{
+        serde_json::from_value(json!({
+            "id": 1,
+            "name": "Rust lang",
+            "chat_id": "@rust",
+            "thread_id": 0,
+        })).unwrap()
+    }
+
+    fn tip() -> Tip{
+        serde_json::from_value(json!({
+            "id": 1,
+            "category_id": 1,
+            "title": "Borrow",
+            "text": "Only one &mut",
+        })).unwrap()
+    }
+
+    fn new_template(kind: Kind, content: &str) -> NewTemplate{
+        NewTemplate{
+            category_id: 1,
+            kind,
+            content: content.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn renders_with_the_default_template(){
+        let pool = database::memory().await;
+
+        let message = Template::render_tip(&pool, &tip(), &category()).await.unwrap();
+
+        assert_eq!(message, "<i>Tip</i>: <b>Borrow</b>\n\nOnly one &mut\n\n#Rust lang");
+        let poll: Poll = serde_json::from_value(json!({
+            "id": 1, "category_id": 1, "question": "Which one moves?"})).unwrap();
+        let question = Template::render_poll(&pool, &poll, &[], &category()).await.unwrap();
+        assert_eq!(question, "Which one moves?\n#Rust lang");
+    }
+
+    #[tokio::test]
+    async fn renders_with_the_template_of_the_category(){
+        let pool = database::memory().await;
+        Template::create(&pool, new_template(Kind::Tip, "{{ tip.title|upper }} in {{ category.name }}"))
+            .await
+            .unwrap();
+
+        let message = Template::render_tip(&pool, &tip(), &category()).await.unwrap();
+
+        assert_eq!(message, "BORROW in Rust lang");
+    }
+
+    #[tokio::test]
+    async fn rejects_a_template_that_does_not_render(){
+        let pool = database::memory().await;
+
+        let result = Template::create(&pool, new_template(Kind::Tip, "{{ tip.title ")).await;
+        assert!(matches!(result, Err(CustomError::Template(_))), "{:?}", result);
+        let result = Template::create(&pool, new_template(Kind::Poll, "{{ poll.question|nope }}")).await;
+        assert!(matches!(result, Err(CustomError::Template(_))), "{:?}", result);
+        assert!(Template::read_all(&pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_category_has_a_template_for_each_kind_at_most(){
+        let pool = database::memory().await;
+        Template::create(&pool, new_template(Kind::Tip, "{{ tip.title }}")).await.unwrap();
+        let poll = Template::create(&pool, new_template(Kind::Poll, "{{ poll.question }}")).await.unwrap();
+
+        let result = Template::create(&pool, new_template(Kind::Tip, "{{ tip.text }}")).await;
+        assert!(matches!(result, Err(CustomError::Conflict(_))), "{:?}", result);
+        let poll = Template{
+            kind: Kind::Tip,
+            content: "{{ category.name }}".to_string(),
+            ..poll
+        };
+        let result = Template::update(&pool, poll).await;
+        assert!(matches!(result, Err(CustomError::Conflict(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn a_missing_template_is_not_found(){
+        let pool = database::memory().await;
+
+        assert!(matches!(Template::read(&pool, 1).await, Err(CustomError::NotFound)));
+        assert!(matches!(Template::delete(&pool, 1).await, Err(CustomError::NotFound)));
+        let template = Template{
+            id: 1,
+            category_id: 1,
+            kind: Kind::Tip,
+            content: "{{ tip.title }}".to_string(),
+        };
+        assert!(matches!(Template::update(&pool, template).await, Err(CustomError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn an_unknown_kind_is_an_error(){
+        let pool = database::memory().await;
+        query("INSERT INTO templates (category_id, kind, content) VALUES (1, 'video', '')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        match Template::read(&pool, 1).await{
+            Err(CustomError::ServerError(e)) => assert!(e.contains("unknown kind video"), "{}", e),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+}
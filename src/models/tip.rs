@@ -1,6 +1,15 @@
+use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
-use sqlx::{sqlite::{SqlitePool, SqliteRow}, query, Row};
-use super::error::CustomError;
+use sqlx::{sqlite::{Sqlite, SqlitePool, SqliteRow}, query, Executor, Row};
+use super::{
+    job,
+    kind::Kind,
+    listing::{Columns, ListParams},
+    publish_at::{self, DUE, DUE_FIRST},
+    strategy::Candidate,
+    validation::{Errors, Validate, MAX_MESSAGE_LENGTH},
+    error::CustomError,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Tip{
@@ -9,7 +18,18 @@ pub struct Tip{
     title: String,
     text: String,
     #[serde(default = "get_default_published")]
-    published: bool
+    published: bool,
+    /// Used by the priority and weighted strategies, the higher the sooner
+    #[serde(default)]
+    priority: i64,
+    /// Not published before this date, and before the rest once it comes
+    #[serde(default, deserialize_with = "publish_at::deserialize")]
+    publish_at: Option<DateTime<Utc>>,
+    /// Kept by the publications, what is sent in a change is ignored
+    #[serde(default)]
+    published_count: i64,
+    #[serde(default)]
+    last_published_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -18,7 +38,11 @@ pub struct NewTip{
     title: String,
     text: String,
     #[serde(default = "get_default_published")]
-    published: bool
+    published: bool,
+    #[serde(default)]
+    priority: i64,
+    #[serde(default, deserialize_with = "publish_at::deserialize")]
+    publish_at: Option<DateTime<Utc>>,
 }
 
 
@@ -27,8 +51,20 @@ pub struct NewTipWithCategory{
     pub category: String,
     pub title: String,
     pub text: String,
+    #[serde(default)]
+    pub priority: i64,
+    #[serde(default, deserialize_with = "publish_at::deserialize")]
+    pub publish_at: Option<DateTime<Utc>>,
 }
 
+const COLUMNS: Columns = Columns{
+    table: "tips",
+    search: &["title", "text"],
+    sort: &["id", "title", "category_id", "published", "priority", "publish_at",
+           "published_count", "last_published_at"],
+    categorized: true,
+};
+
 fn get_default_published() -> bool{
     false
 }
@@ -40,8 +76,61 @@ impl NewTip{
             title,
             text,
             published: false,
+            priority: 0,
+            publish_at: None,
         }
     }
+
+    pub fn set_priority(&mut self, priority: i64){
+        self.priority = priority;
+    }
+
+    pub fn set_publish_at(&mut self, publish_at: Option<DateTime<Utc>>){
+        self.publish_at = publish_at;
+    }
+}
+
+impl Candidate for Tip{
+    fn get_id(&self) -> i64{
+        self.id
+    }
+
+    fn get_priority(&self) -> i64{
+        self.priority
+    }
+
+    fn get_publish_at(&self) -> Option<DateTime<Utc>>{
+        self.publish_at
+    }
+}
+
+/// The title and the text are sent together in a single message
+fn check_tip(errors: &mut Errors, title: &str, text: &str){
+    errors.text("title", title, MAX_MESSAGE_LENGTH);
+    errors.text("text", text, MAX_MESSAGE_LENGTH);
+    if title.chars().count() + text.chars().count() > MAX_MESSAGE_LENGTH {
+        errors.add("text", format!("the title and the text can't be longer than {} characters",
+            MAX_MESSAGE_LENGTH));
+    }
+}
+
+impl Validate for NewTip{
+    fn check(&self, errors: &mut Errors){
+        check_tip(errors, &self.title, &self.text);
+    }
+}
+
+impl Validate for NewTipWithCategory{
+    fn check(&self, errors: &mut Errors){
+        errors.required("category", &self.category);
+        check_tip(errors, &self.title, &self.text);
+    }
+}
+
+impl Validate for Tip{
+    fn check(&self, errors: &mut Errors){
+        check_tip(errors, &self.title, &self.text);
+    }
 }
 
 impl Tip{
@@ -51,14 +140,30 @@ impl Tip{
             category_id: row.get("category_id"),
             title: row.get("title"),
             text: row.get("text"),
-            published: row.get("published")
+            published: row.get("published"),
+            priority: row.get("priority"),
+            publish_at: row.get("publish_at"),
+            published_count: row.get("published_count"),
+            last_published_at: row.get("last_published_at"),
         }
     }
 
+    pub fn get_id(&self) -> i64{
+        self.id
+    }
+
     pub fn get_category_id(&self) -> i64{
         self.category_id
     }
 
+    pub fn set_id(&mut self, id: i64){
+        self.id = id;
+    }
+
+    pub fn set_category_id(&mut self, category_id: i64){
+        self.category_id = category_id;
+    }
+
     pub fn get_title(&self) -> &str{
         &self.title
     }
@@ -67,23 +172,24 @@ impl Tip{
         &self.text
     }
 
-    pub fn set_published(&mut self, publised: bool
-    ){
-        self.published = publised;
+    pub fn get_published(&self) -> bool{
+        self.published
     }
 
-    pub async fn create(pool: &SqlitePool, new_tip: NewTip)
+    pub async fn create<'e, E: Executor<'e, Database = Sqlite>>(executor: E, new_tip: NewTip)
             -> Result<Tip, CustomError>{
         tracing::info!("Data: {:?}", new_tip);
-        let sql = "INSERT INTO tips (category_id, title, text, published)
-                   VALUES ($1, $2, $3, $4) RETURNING *;";
+        let sql = "INSERT INTO tips (category_id, title, text, published, priority, publish_at)
+                   VALUES ($1, $2, $3, $4, $5, $6) RETURNING *;";
         query(sql)
             .bind(new_tip.category_id)
             .bind(new_tip.title)
             .bind(new_tip.text)
             .bind(new_tip.published)
+            .bind(new_tip.priority)
+            .bind(new_tip.publish_at)
             .map(Self::from_row)
-            .fetch_one(pool)
+            .fetch_one(executor)
             .await
             .map_err(|e| {
                 CustomError::ServerError(e.to_string())
@@ -112,9 +218,55 @@ impl Tip{
             })
     }
 
+    /// Reads a page of the tips and how many there are with the filters
+    pub async fn read_page(pool: &SqlitePool, params: &ListParams) -> Result<(Vec<Tip>, i64), CustomError>{
+        params.read(pool, &COLUMNS, |row| Ok(Self::from_row(row))).await
+    }
+
+    /// The next tip that can be published, the scheduled ones that are due
+    /// before the rest
     pub async fn read_not_published(pool: &SqlitePool) -> Result<Option<Tip>, CustomError>{
-        let sql = "SELECT * FROM tips WHERE published = FALSE ORDER BY id LIMIT 1";
-        query(sql)
+        let sql = format!("SELECT * FROM tips WHERE published = FALSE AND {} AND {}
+                           ORDER BY {}, id LIMIT 1", DUE, job::without_open_job(Kind::Tip, "tips"),
+                           DUE_FIRST);
+        query(&sql)
+            .map(Self::from_row)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// Every tip of the category not published yet that is due, the strategy
+    /// of the category chooses which one goes next
+    pub async fn read_not_published_in_category(pool: &SqlitePool, category_id: i64) -> Result<Vec<Tip>, CustomError>{
+        let sql = format!("SELECT * FROM tips WHERE published = FALSE AND category_id = $1
+                           AND {} AND {} ORDER BY id", DUE, job::without_open_job(Kind::Tip, "tips"));
+        query(&sql)
+            .bind(category_id)
+            .map(Self::from_row)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// The tip published the longest ago, or never, of a category that
+    /// recycles, once its cooldown has passed
+    pub async fn read_recyclable(pool: &SqlitePool, category_id: Option<i64>) -> Result<Option<Tip>, CustomError>{
+        let sql = format!("SELECT tips.* FROM tips JOIN categories ON categories.id = tips.category_id
+                   WHERE tips.published = TRUE AND categories.recycle = TRUE AND {}
+                   AND ($1 IS NULL OR tips.category_id = $1)
+                   AND (tips.last_published_at IS NULL
+                        OR datetime(tips.last_published_at) <=
+                           datetime('now', '-' || categories.cooldown || ' days'))
+                   ORDER BY tips.last_published_at IS NOT NULL,
+                            datetime(tips.last_published_at), tips.id
+                   LIMIT 1", job::without_open_job(Kind::Tip, "tips"));
+        query(&sql)
+            .bind(category_id)
             .map(Self::from_row)
             .fetch_optional(pool)
             .await
@@ -123,17 +275,98 @@ impl Tip{
             })
     }
 
-    pub async fn update(pool: &SqlitePool, tip: Tip) -> Result<Tip, CustomError>{
+    /// Marks the tip as published now, once more
+    pub async fn mark_published<'e, E: Executor<'e, Database = Sqlite>>(executor: E, id: i64)
+            -> Result<Tip, CustomError>{
+        let sql = "UPDATE tips SET published = TRUE, published_count = published_count + 1,
+                   last_published_at = $2 WHERE id = $1 RETURNING *;";
+        query(sql)
+            .bind(id)
+            .bind(Utc::now())
+            .map(Self::from_row)
+            .fetch_one(executor)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// The tips of the category scheduled for a date that hasn't come yet,
+    /// the earliest first
+    pub async fn read_scheduled_in_category(pool: &SqlitePool, category_id: i64) -> Result<Vec<Tip>, CustomError>{
+        let sql = format!("SELECT * FROM tips WHERE published = FALSE AND category_id = $1
+                           AND NOT {} ORDER BY datetime(publish_at), id", DUE);
+        query(&sql)
+            .bind(category_id)
+            .map(Self::from_row)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    pub async fn update<'e, E: Executor<'e, Database = Sqlite>>(executor: E, tip: Tip) -> Result<Tip, CustomError>{
         let sql = "UPDATE tips SET category_id = $2, title = $3, text = $4,
-                   published = $5 WHERE id = $1 RETURNING * ;";
+                   published = $5, priority = $6, publish_at = $7 WHERE id = $1 RETURNING * ;";
         query(sql)
             .bind(tip.id)
             .bind(tip.category_id)
             .bind(tip.title)
             .bind(tip.text)
             .bind(tip.published)
+            .bind(tip.priority)
+            .bind(tip.publish_at)
             .map(Self::from_row)
-            .fetch_one(pool)
+            .fetch_one(executor)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error: {}", e);
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// Creates a copy of the tip of a backup, with a new id but with what the
+    /// publications have kept
+    pub async fn restore<'e, E: Executor<'e, Database = Sqlite>>(executor: E, tip: Tip) -> Result<Tip, CustomError>{
+        let sql = "INSERT INTO tips (category_id, title, text, published, priority, publish_at,
+                   published_count, last_published_at)
+                   VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *;";
+        query(sql)
+            .bind(tip.category_id)
+            .bind(tip.title)
+            .bind(tip.text)
+            .bind(tip.published)
+            .bind(tip.priority)
+            .bind(tip.publish_at)
+            .bind(tip.published_count)
+            .bind(tip.last_published_at)
+            .map(Self::from_row)
+            .fetch_one(executor)
+            .await
+            .map_err(|e| {
+                CustomError::ServerError(e.to_string())
+            })
+    }
+
+    /// Overwrites the tip with the one of a backup, what the publications
+    /// have kept too
+    pub async fn replace<'e, E: Executor<'e, Database = Sqlite>>(executor: E, tip: Tip) -> Result<Tip, CustomError>{
+        let sql = "UPDATE tips SET category_id = $2, title = $3, text = $4,
+                   published = $5, priority = $6, publish_at = $7, published_count = $8,
+                   last_published_at = $9 WHERE id = $1 RETURNING * ;";
+        query(sql)
+            .bind(tip.id)
+            .bind(tip.category_id)
+            .bind(tip.title)
+            .bind(tip.text)
+            .bind(tip.published)
+            .bind(tip.priority)
+            .bind(tip.publish_at)
+            .bind(tip.published_count)
+            .bind(tip.last_published_at)
+            .map(Self::from_row)
+            .fetch_one(executor)
             .await
             .map_err(|e| {
                 tracing::error!("Error: {}", e);
@@ -0,0 +1,111 @@
+use std::fmt;
+use serde::Serialize;
+use super::error::CustomError;
+
+/// Longest question of a poll accepted by Telegram
+pub const MAX_QUESTION_LENGTH: usize = 300;
+/// Longest option of a poll accepted by Telegram
+pub const MAX_OPTION_LENGTH: usize = 100;
+pub const MIN_OPTIONS: usize = 2;
+pub const MAX_OPTIONS: usize = 10;
+/// Longest message accepted by Telegram
+pub const MAX_MESSAGE_LENGTH: usize = 4096;
+
+/// What is wrong with a field of a request
+#[derive(Debug, Serialize, Clone)]
+pub struct FieldError{
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for FieldError{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Something that has to be checked before it is stored
+pub trait Validate{
+    fn check(&self, errors: &mut Errors);
+
+    fn validate(&self) -> Result<(), CustomError>{
+        let mut errors = Errors::default();
+        self.check(&mut errors);
+        errors.into_result()
+    }
+}
+
+/// The errors found in the fields of a request
+#[derive(Debug, Default)]
+pub struct Errors(Vec<FieldError>);
+
+impl Errors{
+    pub fn add(&mut self, field: &str, message: String){
+        self.0.push(FieldError{
+            field: field.to_string(),
+            message,
+        });
+    }
+
+    /// Checks the field is not blank
+    pub fn required(&mut self, field: &str, value: &str) -> bool{
+        if value.trim().is_empty() {
+            self.add(field, "can't be empty".to_string());
+            return false;
+        }
+        true
+    }
+
+    /// Checks the field is set, for those only needed by some backends
+    pub fn required_by(&mut self, field: &str, value: Option<&str>, backend: &str){
+        if value.map(|value| value.trim().is_empty()).unwrap_or(true) {
+            self.add(field, format!("is needed to publish in {}", backend));
+        }
+    }
+
+    /// Checks the field is not blank and is not longer than `max` characters
+    pub fn text(&mut self, field: &str, value: &str, max: usize){
+        if self.required(field, value) {
+            self.max_length(field, value, max);
+        }
+    }
+
+    pub fn max_length(&mut self, field: &str, value: &str, max: usize){
+        if value.chars().count() > max {
+            self.add(field, format!("can't be longer than {} characters", max));
+        }
+    }
+
+    /// Checks the options of a quiz, given as their text and whether they
+    /// are the right one. Returns the position of the right option when
+    /// there is exactly one.
+    pub fn answers<'a, I>(&mut self, answers: I) -> Option<usize>
+    where I: IntoIterator<Item = (&'a str, bool)>{
+        let mut count = 0;
+        let mut correct = Vec::new();
+        for (index, (text, isok)) in answers.into_iter().enumerate(){
+            self.text(&format!("answers[{}].text", index), text, MAX_OPTION_LENGTH);
+            if isok {
+                correct.push(index);
+            }
+            count += 1;
+        }
+        if !(MIN_OPTIONS..=MAX_OPTIONS).contains(&count) {
+            self.add("answers", format!("a poll needs from {} to {} answers",
+                MIN_OPTIONS, MAX_OPTIONS));
+        }
+        if correct.len() != 1 {
+            self.add("answers", "a quiz needs exactly one right answer".to_string());
+            return None;
+        }
+        correct.first().copied()
+    }
+
+    pub fn into_result(self) -> Result<(), CustomError>{
+        if self.0.is_empty() {
+            Ok(())
+        }else{
+            Err(CustomError::Validation(self.0))
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::sync::Arc;
+use chrono::{Duration, Utc};
+use rand::Rng;
+use tokio::time;
+use tracing::{debug, error, info};
+
+use crate::{
+    http::{
+        AppState,
+        publish::{publish_next_tip, publish_next_poll},
+    },
+    models::{
+        kind::Kind,
+        schedule::Schedule,
+        error::CustomError,
+    },
+};
+
+/// How often the schedules are checked
+const TICK: u64 = 30;
+
+/// Starts the background task that publishes the content following the
+/// schedules stored in the database.
+pub fn spawn(app_state: Arc<AppState>) -> tokio::task::JoinHandle<()>{
+    tokio::spawn(async move {
+        info!("⏰ Scheduler started");
+        let mut interval = time::interval(time::Duration::from_secs(TICK));
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            if let Err(e) = tick(&app_state).await{
+                error!("Scheduler error: {}", e);
+            }
+        }
+    })
+}
+
+async fn tick(app_state: &Arc<AppState>) -> Result<(), CustomError>{
+    let now = Utc::now();
+    // A run is on time if it has been due for less than two ticks
+    let grace = Duration::seconds(2 * TICK as i64);
+    for schedule in Schedule::read_active(&app_state.pool).await?{
+        let runs = match schedule.pending_runs(now, grace){
+            Ok(runs) => runs,
+            Err(e) => {
+                error!("Schedule {} is not valid: {}", schedule.get_id(), e);
+                continue;
+            }
+        };
+        if runs == 0 {
+            continue;
+        }
+        // Mark the schedule as run before publishing, so a slow publication
+        // or a long jitter doesn't trigger it again in the next tick
+        Schedule::set_last_run(&app_state.pool, schedule.get_id(), now).await?;
+        debug!("Schedule {} runs {} time(s)", schedule.get_id(), runs);
+        let app_state = app_state.clone();
+        tokio::spawn(async move {
+            for _ in 0..runs {
+                if schedule.get_jitter() > 0 {
+                    let jitter = rand::thread_rng().gen_range(0..=schedule.get_jitter()) as u64;
+                    debug!("Schedule {} waits {} seconds", schedule.get_id(), jitter);
+                    time::sleep(time::Duration::from_secs(jitter)).await;
+                }
+                run(&app_state, &schedule).await;
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn run(app_state: &AppState, schedule: &Schedule){
+    let category_id = Some(schedule.get_category_id());
+    let result = match schedule.get_kind(){
+        Kind::Tip => publish_next_tip(app_state, category_id).await.map(|_| ()),
+        Kind::Poll => publish_next_poll(app_state, category_id).await.map(|_| ()),
+    };
+    match result{
+        Ok(()) => info!("Schedule {} published a {}", schedule.get_id(), schedule.get_kind()),
+        Err(CustomError::NotFound) => info!("Schedule {}: nothing to publish", schedule.get_id()),
+        Err(e) => error!("Schedule {} failed: {}", schedule.get_id(), e),
+    }
+}
@@ -0,0 +1,84 @@
+//! Local HTTP server that stands in for the APIs of the backends in the
+//! tests. It answers with the responses it is given, in order, and keeps the
+//! requests it receives.
+
+use std::{collections::VecDeque, net::TcpListener, sync::{Arc, Mutex}};
+use axum::{
+    Router,
+    Json,
+    body::Bytes,
+    extract::State,
+    http::{StatusCode, Uri, HeaderMap},
+};
+use serde_json::Value;
+
+/// A request received by the stub
+#[derive(Debug, Clone)]
+pub struct Request{
+    pub path: String,
+    pub authorization: Option<String>,
+    pub body: Value,
+}
+
+#[derive(Debug, Default)]
+struct Exchanges{
+    responses: VecDeque<(StatusCode, Value)>,
+    requests: Vec<Request>,
+}
+
+pub struct Stub{
+    pub url: String,
+    exchanges: Arc<Mutex<Exchanges>>,
+}
+
+impl Stub{
+    /// Starts the stub in a free port. The last response is repeated once
+    /// the others have been used.
+    pub fn start(responses: Vec<(u16, Value)>) -> Self{
+        let exchanges = Arc::new(Mutex::new(Exchanges{
+            responses: responses.into_iter()
+                .map(|(status, body)| (StatusCode::from_u16(status).unwrap(), body))
+                .collect(),
+            requests: Vec::new(),
+        }));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let app = Router::new()
+            .fallback(answer)
+            .with_state(exchanges.clone());
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        Self{
+            url,
+            exchanges,
+        }
+    }
+
+    pub fn requests(&self) -> Vec<Request>{
+        self.exchanges.lock().unwrap().requests.clone()
+    }
+}
+
+async fn answer(
+    State(exchanges): State<Arc<Mutex<Exchanges>>>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, Json<Value>){
+    let mut exchanges = exchanges.lock().unwrap();
+    exchanges.requests.push(Request{
+        path: uri.path().to_string(),
+        authorization: headers.get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string()),
+        body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+    });
+    let (status, body) = if exchanges.responses.len() > 1 {
+        exchanges.responses.pop_front().unwrap()
+    }else{
+        exchanges.responses.front().cloned().unwrap_or((StatusCode::OK, Value::Null))
+    };
+    (status, Json(body))
+}
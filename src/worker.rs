@@ -0,0 +1,55 @@
+use std::sync::Arc;
+use tokio::time;
+use tracing::{error, info, warn};
+
+use crate::{
+    http::{
+        AppState,
+        publish::run_job,
+    },
+    models::{
+        job::Job,
+        error::CustomError,
+    },
+};
+
+/// How often the pending jobs are looked for
+const TICK: u64 = 5;
+
+/// Fails the jobs left claimed by a server or a command that stopped while
+/// sending them. Only the ones claimed longer than the lease ago, the rest
+/// may still be being sent by another process.
+pub async fn recover(app_state: &AppState) -> Result<(), CustomError>{
+    let interrupted = Job::interrupt(&app_state.pool, app_state.lease).await?;
+    if interrupted > 0 {
+        warn!("{} job(s) were interrupted while sending, retry or cancel them", interrupted);
+    }
+    Ok(())
+}
+
+/// Starts the background task that sends the pending jobs, the ones put
+/// back to be retried, and fails the ones interrupted.
+pub fn spawn(app_state: Arc<AppState>) -> tokio::task::JoinHandle<()>{
+    tokio::spawn(async move {
+        info!("📮 Worker started");
+        let mut interval = time::interval(time::Duration::from_secs(TICK));
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            if let Err(e) = tick(&app_state).await{
+                error!("Worker error: {}", e);
+            }
+        }
+    })
+}
+
+async fn tick(app_state: &AppState) -> Result<(), CustomError>{
+    recover(app_state).await?;
+    while let Some(job) = Job::claim(&app_state.pool).await?{
+        match run_job(app_state, &job).await{
+            Ok(()) => info!("Job {} sent the {} {}", job.get_id(), job.get_kind(), job.get_item_id()),
+            Err(e) => error!("Job {} failed: {}", job.get_id(), e),
+        }
+    }
+    Ok(())
+}